@@ -0,0 +1,162 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::game::{self, game_stage, GameRng, GameStageExt, GameState, Level, TICK_SECONDS};
+use crate::maze::{Maze, Position};
+use crate::pacman::Pacman;
+use crate::scoring::{ScoreEvent, ScoreReason};
+
+// dots eaten in a level before each bonus fruit shows up
+pub const FRUIT_DOTS: [u32; 2] = [70, 170];
+
+// fruit left uneaten goes away after somewhere between these many seconds
+pub const FRUIT_MIN_SECONDS: f32 = 9.;
+pub const FRUIT_MAX_SECONDS: f32 = 10.;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum FruitKind {
+    Cherry,
+    Strawberry,
+    Orange,
+    Apple,
+    Melon,
+    Galaxian,
+    Bell,
+    Key,
+}
+
+impl FruitKind {
+    // The bonus fruit of each level, as in the arcade.
+    pub fn for_level(level: u32) -> Self {
+        match level {
+            0..=1 => Self::Cherry,
+            2 => Self::Strawberry,
+            3..=4 => Self::Orange,
+            5..=6 => Self::Apple,
+            7..=8 => Self::Melon,
+            9..=10 => Self::Galaxian,
+            11..=12 => Self::Bell,
+            _ => Self::Key,
+        }
+    }
+    pub fn points(self) -> u32 {
+        match self {
+            Self::Cherry => 100,
+            Self::Strawberry => 300,
+            Self::Orange => 500,
+            Self::Apple => 700,
+            Self::Melon => 1000,
+            Self::Galaxian => 2000,
+            Self::Bell => 3000,
+            Self::Key => 5000,
+        }
+    }
+}
+
+pub struct Fruit {
+    pub kind: FruitKind,
+    // runs out when the fruit goes away
+    pub timer: Timer,
+}
+
+// Dots eaten so far this level, counting up to the next fruit.
+#[derive(Default)]
+pub struct FruitDots(pub u32);
+
+// Bonus fruit, which turns up on the maze's fruit tile twice a level and is
+// gone again after a few seconds.
+pub struct FruitPlugin;
+
+impl Plugin for FruitPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        game::add_core(app);
+        app.add_resource(FruitDots::default())
+            .add_game_system(game_stage::EATING, fruit_eating.system())
+            .add_game_system(game_stage::EATING, fruit_release.system())
+            .add_game_system(game_stage::MODE, fruit_timer.system())
+            .on_game_state_enter(GameState::Dying, fruit_clear.system())
+            .on_game_state_enter(GameState::LevelClear, fruit_reset.system())
+            .on_game_state_exit(GameState::Title, fruit_reset.system());
+    }
+}
+
+fn fruit_eating(
+    commands: &mut Commands,
+    mut score_events: ResMut<Events<ScoreEvent>>,
+    fruits: Query<(Entity, &Fruit, &Position)>,
+    pacmans: Query<&Pacman>,
+) {
+    if let Some(pacman) = pacmans.iter().next() {
+        for (entity, fruit, fruit_pos) in fruits.iter() {
+            if pacman.eats(*fruit_pos) {
+                commands.despawn(entity);
+                score_events.send(ScoreEvent{points: fruit.kind.points(), reason: ScoreReason::Fruit, position: *fruit_pos});
+            }
+        }
+    }
+}
+
+// Counts the dots Pac-Man eats, and puts out the level's fruit whenever the
+// count reaches one of `FRUIT_DOTS`.
+fn fruit_release(
+    commands: &mut Commands,
+    maze: Res<Maze>,
+    level: Res<Level>,
+    mut rng: ResMut<GameRng>,
+    mut fruit_dots: ResMut<FruitDots>,
+    mut dot_reader: Local<EventReader<ScoreEvent>>,
+    score_events: Res<Events<ScoreEvent>>,
+) {
+    let dots = dot_reader
+        .iter(&score_events)
+        .filter(|event| event.reason == ScoreReason::Food || event.reason == ScoreReason::Energy)
+        .count() as u32;
+    let spawn = match maze.fruit_spawn {
+        Some(spawn) => spawn,
+        None => return,
+    };
+    for _ in 0..dots {
+        fruit_dots.0 += 1;
+        if FRUIT_DOTS.contains(&fruit_dots.0) {
+            let seconds = rng.rng.gen_range(FRUIT_MIN_SECONDS..FRUIT_MAX_SECONDS);
+            commands.spawn((
+                Fruit{kind: FruitKind::for_level(level.0), timer: Timer::from_seconds(seconds, false)},
+                spawn,
+            ));
+        }
+    }
+}
+
+fn fruit_timer(
+    commands: &mut Commands,
+    mut fruits: Query<(Entity, &mut Fruit)>,
+) {
+    for (entity, mut fruit) in fruits.iter_mut() {
+        fruit.timer.tick(TICK_SECONDS);
+        if fruit.timer.finished() {
+            commands.despawn(entity);
+        }
+    }
+}
+
+// Fruit doesn't outlast Pac-Man.
+fn fruit_clear(
+    commands: &mut Commands,
+    fruits: Query<Entity, With<Fruit>>,
+) {
+    for entity in fruits.iter() {
+        commands.despawn(entity);
+    }
+}
+
+// A new level, or a new game, starts counting dots from nothing.
+fn fruit_reset(
+    commands: &mut Commands,
+    mut fruit_dots: ResMut<FruitDots>,
+    fruits: Query<Entity, With<Fruit>>,
+) {
+    fruit_dots.0 = 0;
+    for entity in fruits.iter() {
+        commands.despawn(entity);
+    }
+}
@@ -0,0 +1,491 @@
+use bevy::{
+    prelude::*,
+    core::FixedTimestep,
+    ecs::{Schedule, State, StateStage, System, SystemStage},
+};
+use rand::{rngs::StdRng, SeedableRng};
+use std::path::PathBuf;
+
+use crate::fruit::FruitPlugin;
+use crate::ghost::{FrightenedTimer, GhostAiPlugin, GhostModeTimer, HouseTimer, Mode, ModeSchedule};
+use crate::high_scores::{HighScorePlugin, InitialsEntry};
+use crate::maze::{Maze, MazePlugin};
+use crate::pacman::PacmanPlugin;
+use crate::replay::{self, Replay, ReplaySession};
+use crate::scoring::{ScoreEvent, ScoringPlugin};
+
+pub const STARTING_LIVES: u32 = 3;
+
+// tiles per second at full speed, actors move at a fraction of this
+pub const BASE_SPEED: f32 = 10.;
+
+// The game advances in ticks of this length, however fast frames are drawn.
+pub const TICK_SECONDS: f32 = 1. / 60.;
+
+pub const GAME_STAGE: &str = "game";
+
+// The stage inside `GAME_STAGE` that moves between `GameState`s, and the
+// stages it runs one after another every tick while the game is `Playing`.
+pub mod game_stage {
+    pub const STATE: &str = "state";
+
+    pub const INPUT: &str = "input";
+    pub const MOVEMENT: &str = "movement";
+    pub const COLLISION: &str = "collision";
+    pub const EATING: &str = "eating";
+    pub const AI: &str = "ai";
+    pub const MODE: &str = "mode";
+}
+
+// How long each pause in the game lasts, in seconds.
+pub const READY_SECONDS: f32 = 2.;
+// Pac-Man and the ghosts freeze for a moment when it is caught, then it
+// shrinks away for the rest of `DEATH_SECONDS`.
+pub const DEATH_FREEZE_SECONDS: f32 = 1.;
+pub const DEATH_SECONDS: f32 = 2.5;
+pub const LEVEL_CLEAR_SECONDS: f32 = 2.;
+
+// The phases a game goes through. Only `Playing` runs the `game_stage`s,
+// the rest wait on a timer or a key.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum GameState {
+    // waiting for a key to start a game
+    Title,
+    // READY! before Pac-Man and the ghosts start moving
+    Ready,
+    Playing,
+    Paused,
+    // Pac-Man was caught
+    Dying,
+    // every dot is eaten, the maze flashes
+    LevelClear,
+    GameOver,
+}
+
+// How the game is run. Put one in as a resource before adding the plugins to
+// change it, the plugins read it when they are built.
+#[derive(Clone)]
+pub struct GameConfig {
+    // tick on the clock, or once per update as fast as the app goes
+    pub realtime: bool,
+    // start on the title screen, or straight into the first game
+    pub title: bool,
+    // seed for all randomness
+    pub seed: u64,
+    // the maze to play, relative to the assets directory or absolute, the
+    // classic one without it
+    pub maze: Option<PathBuf>,
+    // where to save a replay of the first game, once it is over
+    pub record: Option<PathBuf>,
+    // a replay to play back, which brings its own seed and maze
+    pub replay: Option<Replay>,
+    // where the high score table is kept, only in memory without one
+    pub high_scores: Option<PathBuf>,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        Self {realtime: true, title: true, seed: rand::random(), maze: None, record: None, replay: None, high_scores: None}
+    }
+}
+
+pub trait GameStageExt {
+    // Adds `system` to one of the `game_stage`s, to run once every tick while
+    // the game is `Playing`.
+    fn add_game_system<S: System<In = (), Out = ()>>(&mut self, stage_name: &'static str, system: S) -> &mut Self;
+    // Adds `system` to run once on the tick the game enters `state`, leaves
+    // it, or every tick it stays in it. `Playing` has its systems added with
+    // `add_game_system` instead.
+    fn on_game_state_enter<S: System<In = (), Out = ()>>(&mut self, state: GameState, system: S) -> &mut Self;
+    fn on_game_state_exit<S: System<In = (), Out = ()>>(&mut self, state: GameState, system: S) -> &mut Self;
+    fn on_game_state_update<S: System<In = (), Out = ()>>(&mut self, state: GameState, system: S) -> &mut Self;
+}
+
+impl GameStageExt for AppBuilder {
+    fn add_game_system<S: System<In = (), Out = ()>>(&mut self, stage_name: &'static str, system: S) -> &mut Self {
+        self.stage(GAME_STAGE, |schedule: &mut Schedule| {
+            schedule.stage(game_stage::STATE, |states: &mut StateStage<GameState>| {
+                states.update_stage(GameState::Playing, |playing: &mut Schedule| {
+                    playing.add_system_to_stage(stage_name, system)
+                })
+            })
+        })
+    }
+    fn on_game_state_enter<S: System<In = (), Out = ()>>(&mut self, state: GameState, system: S) -> &mut Self {
+        self.stage(GAME_STAGE, |schedule: &mut Schedule| {
+            schedule.stage(game_stage::STATE, |states: &mut StateStage<GameState>| states.on_state_enter(state, system))
+        })
+    }
+    fn on_game_state_exit<S: System<In = (), Out = ()>>(&mut self, state: GameState, system: S) -> &mut Self {
+        self.stage(GAME_STAGE, |schedule: &mut Schedule| {
+            schedule.stage(game_stage::STATE, |states: &mut StateStage<GameState>| states.on_state_exit(state, system))
+        })
+    }
+    fn on_game_state_update<S: System<In = (), Out = ()>>(&mut self, state: GameState, system: S) -> &mut Self {
+        self.stage(GAME_STAGE, |schedule: &mut Schedule| {
+            schedule.stage(game_stage::STATE, |states: &mut StateStage<GameState>| states.on_state_update(state, system))
+        })
+    }
+}
+
+// The game stage and the state all the game plugins share, with the moves
+// between states. Whichever plugin is added first sets it up.
+pub(crate) fn add_core(app: &mut AppBuilder) {
+    if app.resources().contains::<Game>() {
+        return;
+    }
+    if !app.resources().contains::<GameConfig>() {
+        app.add_resource(GameConfig::default());
+    }
+    if !app.resources().contains::<Maze>() {
+        app.add_resource(Maze::default());
+    }
+    let config = app.resources().get::<GameConfig>().unwrap().clone();
+
+    let playing = Schedule::default()
+        .with_stage(game_stage::INPUT, SystemStage::serial())
+        .with_stage(game_stage::MOVEMENT, SystemStage::serial())
+        .with_stage(game_stage::COLLISION, SystemStage::serial())
+        .with_stage(game_stage::EATING, SystemStage::serial())
+        .with_stage(game_stage::AI, SystemStage::serial())
+        .with_stage(game_stage::MODE, SystemStage::serial());
+    let mut states = StateStage::<GameState>::default()
+        .with_update_stage(GameState::Playing, playing);
+    for &state in [GameState::Ready, GameState::Dying, GameState::LevelClear].iter() {
+        states = states.with_update_stage(state, SystemStage::serial());
+    }
+    let mut schedule = Schedule::default();
+    if config.realtime {
+        schedule = schedule.with_run_criteria(FixedTimestep::step(TICK_SECONDS as f64));
+    }
+    schedule = schedule.with_stage(game_stage::STATE, states);
+
+    let first_state = if config.title { GameState::Title } else { GameState::Ready };
+    let difficulty = Difficulty::for_level(1);
+    let mode_schedule = ModeSchedule::for_level(1);
+    app.add_resource(GhostModeTimer(mode_schedule.timer()))
+        .add_resource(FrightenedTimer(Timer::from_seconds(difficulty.frightened_seconds, false)))
+        .add_resource(HouseTimer(Timer::from_seconds(difficulty.release_seconds, false)))
+        .add_resource(difficulty)
+        .add_resource(Game{mode:mode_schedule.mode(), frightened: false, ghost_combo: 0})
+        .add_resource(Level(1))
+        .add_resource(mode_schedule)
+        .add_resource(Lives(STARTING_LIVES))
+        .add_resource(Tick(0))
+        .add_resource(GameRng::new(config.seed))
+        .add_resource(State::new(first_state))
+        .add_startup_system(log_seed.system())
+        .add_event::<ScoreEvent>()
+        .add_event::<ExtraLifeEvent>()
+        .add_stage_after(stage::UPDATE, GAME_STAGE, schedule);
+    add_state_changes(app);
+}
+
+// Everything that moves the game from one `GameState` to the next, so a game
+// made of any of the plugins goes through them all.
+fn add_state_changes(app: &mut AppBuilder) {
+    if app.resources().contains::<Input<KeyCode>>() {
+        app.add_system(state_keys.system());
+    }
+    app.add_resource(ReadyTimer(Timer::from_seconds(READY_SECONDS, false)))
+        .add_resource(DeathTimer(Timer::from_seconds(DEATH_SECONDS, false)))
+        .add_resource(LevelClearTimer(Timer::from_seconds(LEVEL_CLEAR_SECONDS, false)))
+        .on_game_state_exit(GameState::Title, new_game.system())
+        .on_game_state_enter(GameState::Ready, ready_start.system())
+        .on_game_state_enter(GameState::Ready, level_difficulty.system())
+        .on_game_state_update(GameState::Ready, ready_timer.system())
+        .on_game_state_enter(GameState::Paused, paused.system())
+        .on_game_state_exit(GameState::Paused, unpaused.system())
+        .on_game_state_enter(GameState::Dying, death_start.system())
+        .on_game_state_update(GameState::Dying, death_timer.system())
+        .on_game_state_update(GameState::Dying, pacman_death.system())
+        .on_game_state_enter(GameState::LevelClear, level_clear_start.system())
+        .on_game_state_update(GameState::LevelClear, level_clear_timer.system())
+        .on_game_state_update(GameState::LevelClear, level_clear.system())
+        .on_game_state_enter(GameState::GameOver, game_over.system());
+}
+
+// The whole game: the maze, Pac-Man, the ghosts, fruit, the score and the
+// high score table, plus replays.
+// Everything in it plays the game, none of it touches a sprite, so it runs
+// just as well without a window.
+pub struct PacmanGamePlugin;
+
+impl Plugin for PacmanGamePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        add_core(app);
+        let config = app.resources().get::<GameConfig>().unwrap().clone();
+        if let Some(replay) = &config.replay {
+            app.add_resource(replay.maze());
+        }
+        app.add_game_system(game_stage::INPUT, replay::replay_input.system())
+            .add_game_system(game_stage::INPUT, replay::replay_record.system())
+            .add_plugin(MazePlugin)
+            .add_plugin(PacmanPlugin)
+            .add_plugin(GhostAiPlugin)
+            .add_plugin(FruitPlugin)
+            .add_plugin(ScoringPlugin)
+            .add_plugin(HighScorePlugin)
+            .add_game_system(game_stage::MODE, replay::replay_hash.system())
+            .add_game_system(game_stage::MODE, tick_count.system())
+            .on_game_state_enter(GameState::GameOver, replay::replay_end.system())
+            .add_resource(ReplaySession::new(&config))
+            .add_system_to_stage(stage::LAST, replay::replay_save.system());
+    }
+}
+
+pub struct Game{
+    pub mode: Mode,
+    // Frightened time runs on its own timer, the scatter/chase schedule is
+    // paused until it runs out.
+    pub frightened: bool,
+    // Ghosts eaten since the last energizer, doubles the points of the next.
+    pub ghost_combo: u32,
+}
+
+// Space starts a game from the title screen, and goes back to it once the
+// game is over, unless `HighScorePlugin` is still waiting on initials. P
+// pauses and unpauses.
+fn state_keys(
+    keyboard_input: Res<Input<KeyCode>>,
+    initials: Option<Res<InitialsEntry>>,
+    mut state: ResMut<State<GameState>>,
+) {
+    let typing = initials.map_or(false, |initials| initials.active);
+    let next = match state.current() {
+        GameState::Title if keyboard_input.just_pressed(KeyCode::Space) => GameState::Ready,
+        GameState::GameOver if keyboard_input.just_pressed(KeyCode::Space) && !typing => GameState::Title,
+        GameState::Playing if keyboard_input.just_pressed(KeyCode::P) => GameState::Paused,
+        GameState::Paused if keyboard_input.just_pressed(KeyCode::P) => GameState::Playing,
+        _ => return,
+    };
+    // taken up on the next tick
+    let _ = state.set_next(next);
+}
+
+pub struct Lives(pub u32);
+
+// Send one when Pac-Man is given a life, for `SoundPlugin` to play the extra
+// life jingle. The game has no rule that gives lives, an app that adds one
+// sends these.
+pub struct ExtraLifeEvent;
+
+pub struct Level(pub u32);
+
+// How hard a level is, later levels have faster ghosts and shorter
+// frightened time, and let the ghosts out of their house sooner.
+pub struct Difficulty {
+    // speeds in tiles per second
+    pub pacman_speed: f32,
+    pub pacman_frightened_speed: f32,
+    pub ghost_speed: f32,
+    pub ghost_frightened_speed: f32,
+    pub ghost_tunnel_speed: f32,
+    pub eyes_speed: f32,
+    pub frightened_seconds: f32,
+    // dots each ghost waits for in the house, indexed by `GhostKind`
+    pub dot_limits: [u32; 4],
+    // how long Pac-Man may go without eating before the next ghost leaves
+    pub release_seconds: f32,
+}
+
+impl Difficulty {
+    pub fn for_level(level: u32) -> Self {
+        const FRIGHTENED_SECONDS: [f32; 18] = [
+            6., 5., 4., 3., 2., 5., 2., 2., 1., 5., 2., 1., 1., 3., 1., 1., 0., 1.,
+        ];
+        let frightened_seconds = *FRIGHTENED_SECONDS
+            .get(level as usize - 1)
+            .unwrap_or(&0.);
+        // fractions of BASE_SPEED for Pac-Man, frightened Pac-Man, ghosts,
+        // frightened ghosts and ghosts in the tunnel
+        let speeds: [f32; 5] = match level {
+            1 => [0.80, 0.90, 0.75, 0.50, 0.40],
+            2..=4 => [0.90, 0.95, 0.85, 0.55, 0.45],
+            5..=20 => [1.00, 1.00, 0.95, 0.60, 0.50],
+            _ => [0.90, 0.90, 0.95, 0.60, 0.50],
+        };
+        let (dot_limits, release_seconds) = match level {
+            1 => ([0, 0, 30, 60], 4.),
+            2 => ([0, 0, 0, 50], 4.),
+            3..=4 => ([0, 0, 0, 0], 4.),
+            _ => ([0, 0, 0, 0], 3.),
+        };
+        Self {
+            pacman_speed: speeds[0] * BASE_SPEED,
+            pacman_frightened_speed: speeds[1] * BASE_SPEED,
+            ghost_speed: speeds[2] * BASE_SPEED,
+            ghost_frightened_speed: speeds[3] * BASE_SPEED,
+            ghost_tunnel_speed: speeds[4] * BASE_SPEED,
+            eyes_speed: 1.6 * BASE_SPEED,
+            frightened_seconds,
+            dot_limits,
+            release_seconds,
+        }
+    }
+}
+
+// Every random choice in the game is drawn from here, so the same seed and
+// the same input always play out the same way.
+pub struct GameRng {
+    pub seed: u64,
+    pub rng: StdRng,
+}
+
+impl GameRng {
+    pub fn new(seed: u64) -> Self {
+        Self {seed, rng: StdRng::seed_from_u64(seed)}
+    }
+}
+
+fn log_seed(rng: Res<GameRng>) {
+    info!("Random seed {}", rng.seed);
+}
+
+// Ticks the game has run for.
+pub struct Tick(pub u64);
+
+fn tick_count(mut tick: ResMut<Tick>) {
+    tick.0 += 1;
+}
+
+pub struct ReadyTimer(pub Timer);
+
+fn ready_start(mut ready_timer: ResMut<ReadyTimer>) {
+    ready_timer.0.reset();
+}
+
+fn ready_timer(
+    mut ready_timer: ResMut<ReadyTimer>,
+    mut state: ResMut<State<GameState>>,
+) {
+    ready_timer.0.tick(TICK_SECONDS);
+    if ready_timer.0.just_finished() {
+        state.set_next(GameState::Playing).unwrap();
+    }
+}
+
+fn paused() {
+    info!("Paused");
+}
+
+fn unpaused() {
+    info!("Unpaused");
+}
+
+pub struct DeathTimer(pub Timer);
+
+impl DeathTimer {
+    // How far Pac-Man has shrunk away, from 0 while it is still frozen to 1
+    // once it is gone.
+    pub fn shrink(&self) -> f32 {
+        let shrinking = self.0.elapsed() - DEATH_FREEZE_SECONDS;
+        (shrinking / (DEATH_SECONDS - DEATH_FREEZE_SECONDS)).max(0.).min(1.)
+    }
+    pub fn frozen(&self) -> bool {
+        self.0.elapsed() < DEATH_FREEZE_SECONDS
+    }
+}
+
+fn death_start(mut death_timer: ResMut<DeathTimer>) {
+    death_timer.0.reset();
+}
+
+fn death_timer(mut death_timer: ResMut<DeathTimer>) {
+    death_timer.0.tick(TICK_SECONDS);
+}
+
+// Once Pac-Man has finished dying, takes a life and goes on to the next one,
+// if there is one. Everyone is put back on their spawn tiles as it starts.
+fn pacman_death(
+    mut game: ResMut<Game>,
+    mut state: ResMut<State<GameState>>,
+    mut lives: ResMut<Lives>,
+    level: Res<Level>,
+    death_timer: Res<DeathTimer>,
+    mut mode_schedule: ResMut<ModeSchedule>,
+    mut ghost_mode_timer: ResMut<GhostModeTimer>,
+) {
+    if !death_timer.0.just_finished() {
+        return;
+    }
+
+    game.frightened = false;
+    mode_schedule.restart(level.0, &mut game, &mut ghost_mode_timer);
+    lives.0 = lives.0.saturating_sub(1);
+    if lives.0 == 0 {
+        state.set_next(GameState::GameOver).unwrap();
+    } else {
+        state.set_next(GameState::Ready).unwrap();
+    }
+}
+
+fn game_over(level: Res<Level>) {
+    info!("Game over on level {}", level.0);
+}
+
+pub struct LevelClearTimer(pub Timer);
+
+fn level_clear_start(mut level_clear_timer: ResMut<LevelClearTimer>) {
+    level_clear_timer.0.reset();
+}
+
+fn level_clear_timer(mut level_clear_timer: ResMut<LevelClearTimer>) {
+    level_clear_timer.0.tick(TICK_SECONDS);
+}
+
+// Starts the next level once the pause after clearing one is over. The
+// maze and ghost plugins lay out fresh dots as the game leaves `LevelClear`,
+// and everyone goes back to their spawn tiles on `Ready`.
+fn level_clear(
+    mut game: ResMut<Game>,
+    mut state: ResMut<State<GameState>>,
+    mut level: ResMut<Level>,
+    level_clear_timer: Res<LevelClearTimer>,
+    mut ghost_mode_timer: ResMut<GhostModeTimer>,
+    mut mode_schedule: ResMut<ModeSchedule>,
+) {
+    if !level_clear_timer.0.just_finished() {
+        return;
+    }
+
+    level.0 += 1;
+    info!("Level {}", level.0);
+
+    game.frightened = false;
+    mode_schedule.restart(level.0, &mut game, &mut ghost_mode_timer);
+    state.set_next(GameState::Ready).unwrap();
+}
+
+// Starts over from the first level with a full set of lives, when a game is
+// started from the title screen.
+fn new_game(
+    mut game: ResMut<Game>,
+    mut level: ResMut<Level>,
+    mut lives: ResMut<Lives>,
+    mut ghost_mode_timer: ResMut<GhostModeTimer>,
+    mut mode_schedule: ResMut<ModeSchedule>,
+) {
+    level.0 = 1;
+    lives.0 = STARTING_LIVES;
+    game.frightened = false;
+    game.ghost_combo = 0;
+    mode_schedule.restart(level.0, &mut game, &mut ghost_mode_timer);
+}
+
+// Speeds the ghosts up and shortens their timers whenever a new level starts.
+fn level_difficulty(
+    level: Res<Level>,
+    mut difficulty: ResMut<Difficulty>,
+    mut frightened_timer: ResMut<FrightenedTimer>,
+    mut house_timer: ResMut<HouseTimer>,
+    mut applied: Local<u32>,
+) {
+    if *applied == level.0 {
+        return;
+    }
+    *applied = level.0;
+    *difficulty = Difficulty::for_level(level.0);
+    frightened_timer.0 = Timer::from_seconds(difficulty.frightened_seconds, false);
+    house_timer.0 = Timer::from_seconds(difficulty.release_seconds, false);
+}
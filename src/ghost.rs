@@ -0,0 +1,434 @@
+use bevy::{prelude::*, ecs::State};
+
+use crate::game::{self, game_stage, Difficulty, Game, GameRng, GameStageExt, GameState, TICK_SECONDS};
+use crate::maze::{Actor, Direction, Maze, Motion, Position, Spawn};
+use crate::pacman::Pacman;
+use crate::scoring::{ScoreEvent, ScoreReason, GHOST_POINTS};
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum GhostKind {
+    Blinky,
+    Pinky,
+    Inky,
+    Clyde,
+}
+
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+pub enum GhostState {
+    Normal,
+    Frightened,
+    // Eaten by Pac-Man, only the eyes are left and they head back home.
+    Eaten,
+    InHouse,
+    Exiting,
+}
+
+pub struct Ghost {
+    pub kind: GhostKind,
+    pub direction: Direction,
+    pub last: Position,
+    pub target: Position,
+    pub scatter_target: Position,
+    pub state: GhostState,
+    pub reverse: bool,
+    // dots counted while first in line to leave the house
+    pub dots: u32,
+}
+
+impl Ghost {
+    pub fn actor(&self) -> Actor {
+        match self.state {
+            GhostState::Eaten | GhostState::Exiting | GhostState::InHouse => Actor::HouseGhost,
+            GhostState::Normal | GhostState::Frightened => Actor::Ghost,
+        }
+    }
+    // Where this ghost heads in chase mode, each ghost hunts Pac-Man in its
+    // own way.
+    pub fn chase_target(&self, own: Position, pacman: Position, pacman_direction: Direction, blinky: Position) -> Position {
+        match self.kind {
+            GhostKind::Blinky => pacman,
+            GhostKind::Pinky => pacman.ahead(pacman_direction, 4),
+            GhostKind::Inky => {
+                let pivot = pacman.ahead(pacman_direction, 2);
+                Position{x: 2 * pivot.x - blinky.x, y: 2 * pivot.y - blinky.y}
+            }
+            GhostKind::Clyde => {
+                if own.euclid_distance(pacman.x, pacman.y) > 8. {
+                    pacman
+                } else {
+                    self.scatter_target
+                }
+            }
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug)]
+pub enum Mode {
+    Scatter,
+    Chase,
+}
+
+// Seconds spent in each scatter/chase phase of a level. Ghosts scatter in
+// the even phases and chase in the odd ones, and once the list runs out
+// they chase for good.
+pub const LEVEL_1_PHASES: [f32; 7] = [7., 20., 7., 20., 5., 20., 5.];
+
+pub const LEVEL_2_PHASES: [f32; 7] = [7., 20., 7., 20., 5., 1033., 1. / 60.];
+
+pub const LEVEL_5_PHASES: [f32; 7] = [5., 20., 5., 20., 5., 1037., 1. / 60.];
+
+pub struct ModeSchedule {
+    pub phases: &'static [f32],
+    pub phase: usize,
+}
+
+impl ModeSchedule {
+    pub fn for_level(level: u32) -> Self {
+        let phases: &'static [f32] = match level {
+            1 => &LEVEL_1_PHASES,
+            2..=4 => &LEVEL_2_PHASES,
+            _ => &LEVEL_5_PHASES,
+        };
+        Self {phases, phase: 0}
+    }
+    pub fn mode(&self) -> Mode {
+        if self.phase % 2 == 0 {
+            Mode::Scatter
+        } else {
+            Mode::Chase
+        }
+    }
+    pub fn finished(&self) -> bool {
+        self.phase >= self.phases.len()
+    }
+    pub fn timer(&self) -> Timer {
+        let seconds = *self.phases.get(self.phase).unwrap_or(&f32::MAX);
+        Timer::from_seconds(seconds, false)
+    }
+    // Back to the first scatter phase, at the start of a level or a life.
+    pub fn restart(&mut self, level: u32, game: &mut Game, ghost_mode_timer: &mut GhostModeTimer) {
+        *self = Self::for_level(level);
+        game.mode = self.mode();
+        ghost_mode_timer.0 = self.timer();
+    }
+}
+
+// The four ghosts: how fast they go, where they head, when they leave their
+// house, the scatter/chase and frightened modes they switch between, and
+// what happens when they run into Pac-Man.
+pub struct GhostAiPlugin;
+
+impl Plugin for GhostAiPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        game::add_core(app);
+        app.add_system(ghost_setup.system())
+            .on_game_state_enter(GameState::Ready, ghost_respawn.system())
+            .on_game_state_exit(GameState::LevelClear, ghost_dots_reset.system())
+            .on_game_state_exit(GameState::Title, ghost_dots_reset.system())
+            .add_game_system(game_stage::MOVEMENT, ghost_speed.system())
+            .add_game_system(game_stage::MOVEMENT, ghost_movement.system())
+            .add_game_system(game_stage::COLLISION, pacman_ghost_collision.system())
+            .add_game_system(game_stage::AI, ghost_house.system())
+            .add_game_system(game_stage::AI, ghost_next_target.system())
+            .add_game_system(game_stage::MODE, ghost_mode_timer.system())
+            .add_game_system(game_stage::MODE, frightened_timer.system())
+            .add_game_system(game_stage::MODE, ghost_mode.system());
+    }
+}
+
+// Puts the ghosts on their spawn tiles once there is a maze to put them on.
+fn ghost_setup(
+    commands: &mut Commands,
+    maze: Res<Maze>,
+    mut spawned: Local<bool>,
+) {
+    if *spawned || maze.is_empty() {
+        return;
+    }
+    *spawned = true;
+    let ghosts = [
+        (GhostKind::Pinky, Direction::Up),
+        (GhostKind::Inky, Direction::Down),
+        (GhostKind::Clyde, Direction::Down),
+        (GhostKind::Blinky, Direction::Left),
+    ];
+    for &(kind, direction) in ghosts.iter() {
+        let position = maze.ghost_spawns[kind as usize];
+        let scatter_target = maze.scatter_target(kind);
+        let state = if position.in_ghost_house(&maze) {
+            GhostState::InHouse
+        } else {
+            GhostState::Normal
+        };
+        commands.spawn((
+            Ghost{kind, direction, last: position, target: scatter_target, scatter_target, state, reverse: false, dots: 0},
+            position,
+            Spawn{position, direction},
+            Motion::at(position),
+        ));
+    }
+}
+
+// Back on the spawn tiles, as at the start of the level, for every new life
+// and level.
+fn ghost_respawn(
+    maze: Res<Maze>,
+    mut ghosts: Query<(Entity, &mut Ghost, &Spawn)>,
+    mut positions: Query<&mut Position>,
+    mut motions: Query<&mut Motion>,
+) {
+    for (entity, mut ghost, spawn) in ghosts.iter_mut() {
+        let mut pos = positions.get_mut(entity).unwrap();
+        *pos = spawn.position;
+        *motions.get_mut(entity).unwrap() = Motion::at(spawn.position);
+        ghost.direction = spawn.direction;
+        ghost.last = spawn.position;
+        ghost.target = ghost.scatter_target;
+        ghost.state = if spawn.position.in_ghost_house(&maze) {
+            GhostState::InHouse
+        } else {
+            GhostState::Normal
+        };
+        ghost.reverse = false;
+    }
+}
+
+// The house starts counting dots over on a new level or game, though not
+// after Pac-Man loses a life.
+fn ghost_dots_reset(mut ghosts: Query<&mut Ghost>) {
+    for mut ghost in ghosts.iter_mut() {
+        ghost.dots = 0;
+    }
+}
+
+// A ghost meets Pac-Man when they share a tile, or when they swapped tiles
+// with each other during the last step. Frightened ghosts get eaten, eyes
+// pass through harmlessly, and any other ghost catches Pac-Man.
+fn pacman_ghost_collision(
+    mut game: ResMut<Game>,
+    mut state: ResMut<State<GameState>>,
+    mut score_events: ResMut<Events<ScoreEvent>>,
+    pacmans: Query<(Entity, &Pacman)>,
+    mut ghosts: Query<(Entity, &mut Ghost)>,
+    positions: Query<&Position>,
+) {
+    if let Some((pacman_entity, pacman)) = pacmans.iter().next() {
+        let pacman_pos = *positions.get(pacman_entity).unwrap();
+        for (ghost_entity, mut ghost) in ghosts.iter_mut() {
+            let ghost_pos = *positions.get(ghost_entity).unwrap();
+            let same_tile = ghost_pos == pacman_pos;
+            let swapped = ghost_pos == pacman.last && ghost.last == pacman_pos;
+            if !same_tile && !swapped {
+                continue;
+            }
+            match ghost.state {
+                GhostState::Frightened => {
+                    ghost.state = GhostState::Eaten;
+                    let points = GHOST_POINTS << game.ghost_combo.min(3);
+                    game.ghost_combo += 1;
+                    score_events.send(ScoreEvent{points, reason: ScoreReason::Ghost, position: ghost_pos});
+                }
+                GhostState::Normal | GhostState::Exiting => {
+                    state.set_next(GameState::Dying).unwrap();
+                    return;
+                }
+                GhostState::Eaten | GhostState::InHouse => {}
+            }
+        }
+    }
+}
+
+fn ghost_speed(
+    maze: Res<Maze>,
+    difficulty: Res<Difficulty>,
+    mut ghosts: Query<(&Ghost, &Position, &mut Motion)>,
+) {
+    for (ghost, pos, mut motion) in ghosts.iter_mut() {
+        motion.speed = match ghost.state {
+            GhostState::Eaten => difficulty.eyes_speed,
+            // ghosts slow down in the tunnels
+            _ if maze.is_tunnel(*pos) => difficulty.ghost_tunnel_speed,
+            GhostState::Frightened => difficulty.ghost_frightened_speed,
+            // bobbing in the house is slower than walking the maze
+            GhostState::InHouse => difficulty.ghost_speed / 2.,
+            _ => difficulty.ghost_speed,
+        };
+    }
+}
+
+fn ghost_movement(
+    maze: Res<Maze>,
+    mut rng: ResMut<GameRng>,
+    mut ghosts: Query<(Entity, &mut Ghost, &mut Motion)>,
+    mut positions: Query<&mut Position>,
+) {
+    for (entity, mut ghost, mut motion) in ghosts.iter_mut() {
+        let mut pos = positions.get_mut(entity).unwrap();
+        motion.progress += motion.speed * TICK_SECONDS;
+        if motion.progress < 1. {
+            continue;
+        }
+        motion.progress = (motion.progress - 1.).min(1.);
+        if ghost.state == GhostState::InHouse {
+            // bob up and down while waiting to be let out
+            ghost.direction = ghost.direction.opposite();
+            continue;
+        }
+        let actor = ghost.actor();
+        let (next_tile, next_dir) = if ghost.reverse && ghost.last != *pos && maze.is_walkable_for(actor, ghost.last) {
+            (ghost.last, ghost.direction.opposite())
+        } else if ghost.state == GhostState::Frightened {
+            pos.choose_random_tile(&maze, ghost.direction, &mut rng.rng)
+        } else if ghost.state == GhostState::Eaten {
+            pos.choose_route_tile(&maze, ghost.direction, maze.house_center, actor)
+        } else if ghost.state == GhostState::Exiting {
+            pos.choose_route_tile(&maze, ghost.direction, maze.house_entrance, actor)
+        } else {
+            pos.choose_next_tile(&maze, ghost.direction, ghost.target, actor)
+        };
+        ghost.reverse = false;
+        ghost.direction = next_dir;
+        ghost.last = *pos;
+        motion.from = *pos;
+
+        *pos = next_tile;
+
+        // revived ghosts come straight back out without waiting their turn
+        if ghost.state == GhostState::Eaten && *pos == maze.house_center {
+            ghost.state = GhostState::Exiting;
+            ghost.direction = Direction::Up;
+        } else if ghost.state == GhostState::Exiting && *pos == maze.house_entrance {
+            ghost.state = GhostState::Normal;
+        }
+    }
+}
+
+pub struct HouseTimer(pub Timer);
+
+// Lets the ghosts waiting in the house out one at a time. Only the first in
+// line counts the dots Pac-Man eats, and leaves once it reaches its limit or
+// once Pac-Man has gone too long without eating.
+fn ghost_house(
+    difficulty: Res<Difficulty>,
+    mut house_timer: ResMut<HouseTimer>,
+    mut dot_reader: Local<EventReader<ScoreEvent>>,
+    score_events: Res<Events<ScoreEvent>>,
+    mut ghosts: Query<&mut Ghost>,
+) {
+    let dots = dot_reader
+        .iter(&score_events)
+        .filter(|event| event.reason == ScoreReason::Food || event.reason == ScoreReason::Energy)
+        .count() as u32;
+    if dots > 0 {
+        house_timer.0.reset();
+    } else {
+        house_timer.0.tick(TICK_SECONDS);
+    }
+
+    let exiting = ghosts.iter_mut().any(|ghost| ghost.state == GhostState::Exiting);
+    let mut waiting: Vec<_> = ghosts
+        .iter_mut()
+        .filter(|ghost| ghost.state == GhostState::InHouse)
+        .collect();
+    waiting.sort_by_key(|ghost| ghost.kind as u32);
+
+    if let Some(ghost) = waiting.first_mut() {
+        ghost.dots += dots;
+        let limit = difficulty.dot_limits[ghost.kind as usize];
+        if !exiting && (ghost.dots >= limit || house_timer.0.finished()) {
+            ghost.state = GhostState::Exiting;
+            ghost.direction = Direction::Up;
+            house_timer.0.reset();
+        }
+    }
+}
+
+fn ghost_next_target(
+    game: ResMut<Game>,
+    mut ghosts: Query<(Entity, &mut Ghost)>,
+    pacmans: Query<(Entity, &Pacman)>,
+    positions: Query<&Position>,
+) {
+    let (pacman_pos, pacman_direction) = match pacmans.iter().next() {
+        Some((pacman_entity, pacman)) => (*positions.get(pacman_entity).unwrap(), pacman.direction),
+        None => return,
+    };
+    // Inky lines himself up with Blinky, so find Blinky first
+    let mut blinky_pos = pacman_pos;
+    for (entity, ghost) in ghosts.iter_mut() {
+        if ghost.kind == GhostKind::Blinky {
+            blinky_pos = *positions.get(entity).unwrap();
+        }
+    }
+    for (entity, mut ghost) in ghosts.iter_mut() {
+        if ghost.state != GhostState::Normal {
+            continue;
+        }
+        if game.mode == Mode::Scatter {
+            ghost.target = ghost.scatter_target; 
+        }
+        else if game.mode == Mode::Chase {
+            let ghost_pos = *positions.get(entity).unwrap();
+            ghost.target = ghost.chase_target(ghost_pos, pacman_pos, pacman_direction, blinky_pos);
+        } 
+    }
+}
+
+pub struct GhostModeTimer(pub Timer);
+
+fn ghost_mode_timer(
+    game: Res<Game>,
+    mode_schedule: Res<ModeSchedule>,
+    mut ghost_mode_timer: ResMut<GhostModeTimer>
+) {
+    if game.frightened || mode_schedule.finished() {
+        return;
+    }
+    ghost_mode_timer.0.tick(TICK_SECONDS);
+}
+
+pub struct FrightenedTimer(pub Timer);
+
+fn frightened_timer(
+    mut game: ResMut<Game>,
+    mut frightened_timer: ResMut<FrightenedTimer>,
+    mut ghosts: Query<&mut Ghost>,
+) {
+    if !game.frightened {
+        return;
+    }
+    frightened_timer.0.tick(TICK_SECONDS);
+    if frightened_timer.0.finished() {
+        game.frightened = false;
+        for mut ghost in ghosts.iter_mut() {
+            if ghost.state == GhostState::Frightened {
+                ghost.state = GhostState::Normal;
+            }
+        }
+    }
+}
+
+// Moves on to the next scatter/chase phase, every ghost out in the maze
+// turns around when the mode changes.
+fn ghost_mode(
+    mut game: ResMut<Game>,
+    mut mode_schedule: ResMut<ModeSchedule>,
+    mut ghost_mode_timer: ResMut<GhostModeTimer>,
+    mut ghosts: Query<&mut Ghost>,
+){
+    if game.frightened || mode_schedule.finished() {
+        return;
+    }
+    if !ghost_mode_timer.0.finished() {
+        return;
+    }
+    mode_schedule.phase += 1;
+    ghost_mode_timer.0 = mode_schedule.timer();
+    game.mode = mode_schedule.mode();
+    for mut ghost in ghosts.iter_mut() {
+        if ghost.state == GhostState::Normal || ghost.state == GhostState::Frightened {
+            ghost.reverse = true;
+        }
+    }
+}
@@ -0,0 +1,534 @@
+use bevy::{prelude::*, ecs::State};
+
+use crate::fruit::{Fruit, FruitKind};
+use crate::game::{DeathTimer, Game, GameState, Level, LevelClearTimer, Lives};
+use crate::ghost::{Ghost, GhostState};
+use crate::high_scores::{HighScores, InitialsEntry};
+use crate::maze::{Direction, HouseGate, Maze, Motion, Position, Wall};
+use crate::pacman::{Energy, Food, Pacman};
+use crate::scoring::Score;
+
+struct Size {
+    width: f32,
+    height: f32,
+}
+
+impl Size {
+    fn square(x: f32) -> Self {
+        Self {
+            width: x,
+            height: x,
+        }
+    }
+}
+
+struct GhostSprites {
+    normal: Handle<TextureAtlas>,
+    scared: Handle<TextureAtlas>,
+    eyes: Handle<TextureAtlas>,
+}
+
+struct MazeMaterials {
+    wall: Handle<ColorMaterial>,
+    food: Handle<ColorMaterial>,
+    energy: Handle<ColorMaterial>,
+    gate: Handle<ColorMaterial>,
+    // indexed by `FruitKind`
+    fruits: [Handle<ColorMaterial>; 8],
+}
+
+const WALL_COLOR: Color = Color::rgb(0.2, 0.6, 1.0);
+
+// how far above and below their tile the ghosts in the house bob, in pixels
+const BOB_PIXELS: f32 = 3.;
+
+// Texture atlases for everything that moves, made once at startup.
+struct SpriteSheets {
+    pacman: Handle<TextureAtlas>,
+    // indexed by `GhostKind`
+    ghosts: [Handle<TextureAtlas>; 4],
+    scared: Handle<TextureAtlas>,
+    eyes: Handle<TextureAtlas>,
+}
+
+// Draws the game, on top of `PacmanGamePlugin` and bevy's `DefaultPlugins`.
+pub struct PacmanGraphicsPlugin;
+
+impl Plugin for PacmanGraphicsPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.add_startup_system(setup.system())
+            .add_startup_system(hud_setup.system())
+            .add_system(position_translation.system())
+            .add_system(size_scaling.system())
+            .add_system(pacman_animate.system())
+            .add_system(pacman_sprite.system())
+            .add_system(ghost_animate.system())
+            .add_system(ghost_visibility.system())
+            .add_system(maze_flash.system())
+            .add_system(hud_update.system())
+            .add_system(fruit_icons.system())
+            // anything spawned during the update gets its sprite straight after
+            .add_system_to_stage(stage::POST_UPDATE, maze_sprites.system())
+            .add_system_to_stage(stage::POST_UPDATE, pacman_sprites.system())
+            .add_system_to_stage(stage::POST_UPDATE, ghost_sprites.system())
+            .add_system_to_stage(stage::POST_UPDATE, dot_sprites.system())
+            .add_system_to_stage(stage::POST_UPDATE, fruit_sprites.system());
+    }
+}
+
+fn setup(
+    commands: &mut Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+) {
+    commands
+        .spawn(Camera2dBundle::default())
+        .spawn(LightBundle {
+            transform: Transform::from_translation(Vec3::new(4.0, 8.0, 4.0)),
+            ..Default::default()
+        });
+
+    commands.insert_resource(MazeMaterials {
+        wall: materials.add(WALL_COLOR.into()),
+        food: materials.add(Color::rgb(1.0, 1.0, 1.0).into()),
+        energy: materials.add(Color::rgb(1.0, 1.0, 1.0).into()),
+        gate: materials.add(Color::rgb(0.5, 0.5, 0.5).into()),
+        fruits: [
+            materials.add(Color::rgb(0.9, 0.0, 0.1).into()),
+            materials.add(Color::rgb(1.0, 0.3, 0.4).into()),
+            materials.add(Color::rgb(1.0, 0.6, 0.0).into()),
+            materials.add(Color::rgb(0.8, 0.1, 0.2).into()),
+            materials.add(Color::rgb(0.3, 0.8, 0.2).into()),
+            materials.add(Color::rgb(1.0, 0.9, 0.1).into()),
+            materials.add(Color::rgb(0.9, 0.8, 0.2).into()),
+            materials.add(Color::rgb(0.3, 0.9, 0.9).into()),
+        ],
+    });
+
+    let mut sheet = |path: &str, columns: usize| {
+        let texture = asset_server.load(path);
+        texture_atlases.add(TextureAtlas::from_grid(texture, Vec2::new(20.0, 20.0), columns, 1))
+    };
+    commands.insert_resource(SpriteSheets {
+        pacman: sheet("pacman-sheet.png", 4),
+        ghosts: [
+            sheet("redghost-sheet.png", 4),
+            sheet("pinkghost-sheet.png", 4),
+            sheet("blueghost-sheet.png", 4),
+            sheet("orangeghost-sheet.png", 4),
+        ],
+        scared: sheet("scaredghost.png", 1),
+        eyes: sheet("eyes-sheet.png", 4),
+    });
+}
+
+fn maze_sprites(
+    commands: &mut Commands,
+    maze_materials: Res<MazeMaterials>,
+    walls: Query<Entity, Added<Wall>>,
+    gates: Query<Entity, Added<HouseGate>>,
+) {
+    for entity in walls.iter() {
+        commands.insert(entity, SpriteBundle {
+            material: maze_materials.wall.clone(),
+            ..Default::default()
+        });
+        commands.insert_one(entity, Size::square(1.0));
+    }
+    for entity in gates.iter() {
+        commands.insert(entity, SpriteBundle {
+            material: maze_materials.gate.clone(),
+            ..Default::default()
+        });
+        commands.insert_one(entity, Size::square(1.0));
+    }
+}
+
+fn pacman_sprites(
+    commands: &mut Commands,
+    sheets: Res<SpriteSheets>,
+    pacmans: Query<Entity, Added<Pacman>>,
+) {
+    for entity in pacmans.iter() {
+        commands.insert(entity, SpriteSheetBundle {
+            texture_atlas: sheets.pacman.clone(),
+            ..Default::default()
+        });
+        commands.insert(entity, (Size::square(1.0), Timer::from_seconds(0.1, true)));
+    }
+}
+
+fn ghost_sprites(
+    commands: &mut Commands,
+    sheets: Res<SpriteSheets>,
+    ghosts: Query<(Entity, &Ghost), Added<Ghost>>,
+) {
+    for (entity, ghost) in ghosts.iter() {
+        let normal = sheets.ghosts[ghost.kind as usize].clone();
+        commands.insert(entity, SpriteSheetBundle {
+            texture_atlas: normal.clone(),
+            ..Default::default()
+        });
+        commands.insert(entity, (
+            GhostSprites{normal, scared: sheets.scared.clone(), eyes: sheets.eyes.clone()},
+            Size::square(1.0),
+        ));
+    }
+}
+
+fn fruit_sprites(
+    commands: &mut Commands,
+    maze_materials: Res<MazeMaterials>,
+    fruits: Query<(Entity, &Fruit), Added<Fruit>>,
+) {
+    for (entity, fruit) in fruits.iter() {
+        commands.insert(entity, SpriteBundle {
+            material: maze_materials.fruits[fruit.kind as usize].clone(),
+            ..Default::default()
+        });
+        commands.insert_one(entity, Size::square(0.8));
+    }
+}
+
+fn dot_sprites(
+    commands: &mut Commands,
+    maze_materials: Res<MazeMaterials>,
+    foods: Query<Entity, Added<Food>>,
+    energies: Query<Entity, Added<Energy>>,
+) {
+    for entity in foods.iter() {
+        commands.insert(entity, SpriteBundle {
+            material: maze_materials.food.clone(),
+            ..Default::default()
+        });
+        commands.insert_one(entity, Size::square(0.1));
+    }
+    for entity in energies.iter() {
+        commands.insert(entity, SpriteBundle {
+            material: maze_materials.energy.clone(),
+            ..Default::default()
+        });
+        commands.insert_one(entity, Size::square(0.4));
+    }
+}
+
+struct ScoreText;
+
+struct HighScoreText;
+
+struct LivesText;
+
+struct ModeText;
+
+// READY!, PAUSED and the like, across the middle of the maze.
+struct MessageText;
+
+struct HighScoreTableText;
+
+// One of the fruit of the last few levels shown along the bottom, this one
+// going back so many levels from the current one.
+struct FruitIcon(u32);
+
+const FRUIT_ICONS: u32 = 7;
+
+fn hud_setup(
+    commands: &mut Commands,
+    asset_server: Res<AssetServer>,
+) {
+    let font = asset_server.load("fonts/DejaVuSansMono-Bold.ttf");
+    let text_style = TextStyle {
+        font_size: 20.0,
+        color: Color::WHITE,
+        ..Default::default()
+    };
+    let hud_text = |value: &str, position: Rect<Val>| TextBundle {
+        style: Style {
+            position_type: PositionType::Absolute,
+            position,
+            ..Default::default()
+        },
+        text: Text {
+            value: value.to_string(),
+            font: font.clone(),
+            style: text_style.clone(),
+        },
+        ..Default::default()
+    };
+    commands
+        .spawn(CameraUiBundle::default())
+        .spawn(hud_text("SCORE 0", Rect {
+            top: Val::Px(5.0),
+            left: Val::Px(10.0),
+            ..Default::default()
+        }))
+        .with(ScoreText)
+        .spawn(hud_text("HIGH SCORE 0", Rect {
+            top: Val::Px(5.0),
+            right: Val::Px(10.0),
+            ..Default::default()
+        }))
+        .with(HighScoreText)
+        .spawn(hud_text("LIVES 3", Rect {
+            bottom: Val::Px(5.0),
+            left: Val::Px(10.0),
+            ..Default::default()
+        }))
+        .with(LivesText)
+        .spawn(hud_text("SCATTER", Rect {
+            bottom: Val::Px(5.0),
+            right: Val::Px(10.0),
+            ..Default::default()
+        }))
+        .with(ModeText)
+        .spawn(hud_text("", Rect {
+            top: Val::Percent(47.0),
+            left: Val::Percent(45.0),
+            ..Default::default()
+        }))
+        .with(MessageText)
+        .spawn(hud_text("", Rect {
+            top: Val::Percent(20.0),
+            left: Val::Percent(42.0),
+            ..Default::default()
+        }))
+        .with(HighScoreTableText);
+    for i in 0..FRUIT_ICONS {
+        commands
+            .spawn(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        bottom: Val::Px(9.0),
+                        right: Val::Px(150.0 + 20.0 * i as f32),
+                        ..Default::default()
+                    },
+                    size: bevy::math::Size::new(Val::Px(14.0), Val::Px(14.0)),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .with(FruitIcon(i));
+    }
+}
+
+fn position_translation(
+    maze: Res<Maze>,
+    mut q: Query<(&Position, Option<&Motion>, Option<&Ghost>, &mut Transform)>,
+) {
+    let translation = |x: i32, y: i32| -> (i32, i32) {
+        let (x2, y2): (i32, i32);
+        if x < maze.width/2  {
+            x2 = ((maze.width/2 - x ) * 20  + (20/2))  * -1
+        } else {
+            x2 = (x - maze.width/2) * 20  - (20/2)
+        }
+        if y < maze.height/2  {
+            y2 = (maze.height/2 - y ) * 20  + (20/2)
+        } else {
+            y2 = ((y - maze.height/2) * 20  - (20/2)) * -1
+        }
+        (x2, y2)
+    };
+    for (pos, motion, ghost, mut transform) in q.iter_mut() {
+        let (x, y): (i32, i32) = translation(pos.x, pos.y);
+        let mut at = Vec2::new(x as f32, y as f32);
+        if let Some(motion) = motion {
+            // slide over from the last tile, unless that was across the tunnel
+            let from = motion.from;
+            if (from.x - pos.x).abs() + (from.y - pos.y).abs() == 1 {
+                let (from_x, from_y) = translation(from.x, from.y);
+                let from_at = Vec2::new(from_x as f32, from_y as f32);
+                at = from_at + (at - from_at) * motion.progress.min(1.);
+            }
+            // ghosts waiting in the house go from one end of their bob to
+            // the other in the time they would take to cross a tile
+            if let Some(ghost) = ghost.filter(|ghost| ghost.state == GhostState::InHouse) {
+                let bob = BOB_PIXELS * (2. * motion.progress.min(1.) - 1.);
+                match ghost.direction {
+                    Direction::Up => at.y += bob,
+                    Direction::Down => at.y -= bob,
+                    _ => {}
+                }
+            }
+        }
+        transform.translation = Vec3::new(
+            at.x,
+            at.y,
+            0.0,
+        );
+    }
+}
+
+fn size_scaling(mut q: Query<(&Size, &mut Sprite)>) {
+    for (sprite_size, mut sprite) in q.iter_mut() {
+        sprite.size = Vec2::new(
+            20 as f32 *sprite_size.width ,
+            20 as f32 *sprite_size.height ,
+        );
+    }
+}
+
+fn pacman_animate(
+    time: Res<Time>,
+    texture_atlases: Res<Assets<TextureAtlas>>,
+    mut query: Query<(&Pacman, &mut Timer, &mut TextureAtlasSprite, &Handle<TextureAtlas>)>,
+) {
+    for (_, mut timer, mut sprite, texture_atlas_handle) in query.iter_mut() {
+        timer.tick(time.delta_seconds());
+        if timer.finished() {
+            let texture_atlas = texture_atlases.get(texture_atlas_handle).unwrap();
+            sprite.index = ((sprite.index as usize + 1) % texture_atlas.textures.len()) as u32;
+        }
+    }
+}
+
+fn ghost_animate(
+    mut query: Query<(&Ghost, &GhostSprites, &mut TextureAtlasSprite, &mut Handle<TextureAtlas>)>,
+) {
+    for (ghost, sprites, mut sprite, mut atlas) in query.iter_mut() {
+        match ghost.state {
+            GhostState::Frightened => {
+                *atlas = sprites.scared.clone();
+                sprite.index = 0;
+                continue;
+            }
+            GhostState::Eaten => *atlas = sprites.eyes.clone(),
+            _ => *atlas = sprites.normal.clone(),
+        }
+        match ghost.direction {
+            Direction::Left => sprite.index = 0,
+            Direction::Up => sprite.index = 1,
+            Direction::Right => sprite.index = 2,
+            Direction::Down => sprite.index = 3,
+        }
+    }
+}
+
+// Turns Pac-Man to face the way it is going, or shrinks and spins it away
+// while it dies. Pac-Man is hidden on the title screen and once the game is
+// over.
+fn pacman_sprite(
+    state: Res<State<GameState>>,
+    death_timer: Res<DeathTimer>,
+    mut pacmans: Query<(&Pacman, &mut Transform, &mut Visible)>,
+) {
+    for (pacman, mut transform, mut visible) in pacmans.iter_mut() {
+        if *state.current() == GameState::Dying {
+            if !death_timer.frozen() {
+                transform.scale = Vec3::splat(1. - death_timer.shrink());
+                transform.rotate(Quat::from_rotation_z(std::f32::consts::PI / 8.));
+            }
+            continue;
+        }
+        let angle = match pacman.direction {
+            Direction::Right => 0.,
+            Direction::Up => std::f32::consts::PI / 2.,
+            Direction::Left => std::f32::consts::PI,
+            Direction::Down => -1. * std::f32::consts::PI / 2.,
+        };
+        transform.scale = Vec3::one();
+        transform.rotation = Quat::from_rotation_z(angle);
+        visible.is_visible = !matches!(state.current(), GameState::Title | GameState::GameOver);
+    }
+}
+
+// The ghosts vanish once Pac-Man starts shrinking away, and stay hidden on
+// the title screen and once the game is over.
+fn ghost_visibility(
+    state: Res<State<GameState>>,
+    death_timer: Res<DeathTimer>,
+    mut ghosts: Query<&mut Visible, With<Ghost>>,
+) {
+    let visible_now = match state.current() {
+        GameState::Dying => death_timer.frozen(),
+        GameState::Title | GameState::GameOver => false,
+        _ => true,
+    };
+    for mut visible in ghosts.iter_mut() {
+        visible.is_visible = visible_now;
+    }
+}
+
+fn hud_update(
+    game: Res<Game>,
+    state: Res<State<GameState>>,
+    score: Res<Score>,
+    lives: Res<Lives>,
+    high_scores: Res<HighScores>,
+    initials: Res<InitialsEntry>,
+    mut texts: QuerySet<(
+        Query<&mut Text, With<ScoreText>>,
+        Query<&mut Text, With<HighScoreText>>,
+        Query<&mut Text, With<LivesText>>,
+        Query<&mut Text, With<ModeText>>,
+        Query<&mut Text, With<MessageText>>,
+        Query<&mut Text, With<HighScoreTableText>>,
+    )>,
+) {
+    for mut text in texts.q0_mut().iter_mut() {
+        text.value = format!("SCORE {}", score.current);
+    }
+    for mut text in texts.q1_mut().iter_mut() {
+        text.value = format!("HIGH SCORE {}", score.high);
+    }
+    for mut text in texts.q2_mut().iter_mut() {
+        text.value = format!("LIVES {}", lives.0);
+    }
+    for mut text in texts.q3_mut().iter_mut() {
+        text.value = if game.frightened {
+            "FRIGHTENED".to_string()
+        } else {
+            format!("{:?}", game.mode).to_uppercase()
+        };
+    }
+    for mut text in texts.q4_mut().iter_mut() {
+        text.value = match state.current() {
+            GameState::Title => "PRESS SPACE".to_string(),
+            GameState::Ready => "READY!".to_string(),
+            GameState::Paused => "PAUSED".to_string(),
+            GameState::GameOver if initials.active => format!("INITIALS {:_<3}", initials.initials),
+            GameState::GameOver => "GAME OVER".to_string(),
+            GameState::Playing | GameState::Dying | GameState::LevelClear => String::new(),
+        };
+    }
+    // the table only shows on the title screen
+    for mut text in texts.q5_mut().iter_mut() {
+        text.value = if *state.current() == GameState::Title {
+            high_scores.entries.iter()
+                .enumerate()
+                .map(|(i, entry)| format!("{:>2} {} {:>7}\n", i + 1, entry.initials, entry.score))
+                .collect()
+        } else {
+            String::new()
+        };
+    }
+}
+
+fn fruit_icons(
+    level: Res<Level>,
+    maze_materials: Res<MazeMaterials>,
+    mut icons: Query<(&FruitIcon, &mut Handle<ColorMaterial>, &mut Visible)>,
+) {
+    for (icon, mut material, mut visible) in icons.iter_mut() {
+        visible.is_visible = level.0 > icon.0;
+        if visible.is_visible {
+            let kind = FruitKind::for_level(level.0 - icon.0);
+            *material = maze_materials.fruits[kind as usize].clone();
+        }
+    }
+}
+
+// Flashes the maze walls during the pause after a level is cleared.
+fn maze_flash(
+    state: Res<State<GameState>>,
+    level_clear_timer: Res<LevelClearTimer>,
+    maze_materials: Res<MazeMaterials>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let flash = *state.current() == GameState::LevelClear && (level_clear_timer.0.elapsed() / 0.25) as u32 % 2 == 1;
+    let color = if flash { Color::WHITE } else { WALL_COLOR };
+    if materials.get(&maze_materials.wall).map_or(false, |wall| wall.color != color) {
+        materials.get_mut(&maze_materials.wall).unwrap().color = color;
+    }
+}
@@ -0,0 +1,212 @@
+use bevy::{prelude::*, window::ReceivedCharacter};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::game::{self, GameConfig, GameStageExt, GameState, Level};
+use crate::scoring::Score;
+
+pub const HIGH_SCORE_VERSION: u32 = 1;
+
+// how many scores the table keeps
+pub const HIGH_SCORE_ENTRIES: usize = 10;
+
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct HighScore {
+    pub initials: String,
+    pub score: u32,
+    pub level: u32,
+}
+
+// The best scores so far, best first, kept in a RON file between sessions.
+// Without a file it only lasts as long as the app.
+#[derive(Default)]
+pub struct HighScores {
+    pub path: Option<PathBuf>,
+    pub entries: Vec<HighScore>,
+}
+
+// What goes in the file. The checksum is over the entries, so a file that
+// was cut short or edited by hand is caught.
+#[derive(Serialize, Deserialize)]
+struct HighScoreFile {
+    version: u32,
+    checksum: u64,
+    entries: Vec<HighScore>,
+}
+
+impl HighScores {
+    // Reads the table from `path`. A file that can't be read is reported and
+    // replaced by an empty table, a missing one is simply an empty table.
+    pub fn load(path: Option<PathBuf>) -> Self {
+        let mut table = Self {path: path.clone(), entries: Vec::new()};
+        let path = match path {
+            Some(path) if path.exists() => path,
+            _ => return table,
+        };
+        match Self::read(&path) {
+            Ok(entries) => table.entries = entries,
+            Err(err) => {
+                error!("High score table {} is unreadable, starting a new one: {}", path.display(), err);
+                if let Err(err) = table.save() {
+                    error!("Couldn't save high scores to {}: {}", path.display(), err);
+                }
+            }
+        }
+        table
+    }
+    pub fn read(path: &Path) -> Result<Vec<HighScore>, anyhow::Error> {
+        let file: HighScoreFile = ron::de::from_str(&std::fs::read_to_string(path)?)?;
+        if file.version != HIGH_SCORE_VERSION {
+            anyhow::bail!("version {} can't be read, expected {}", file.version, HIGH_SCORE_VERSION);
+        }
+        if file.checksum != checksum(&file.entries) {
+            anyhow::bail!("the checksum doesn't match");
+        }
+        if file.entries.len() > HIGH_SCORE_ENTRIES {
+            anyhow::bail!("{} entries, expected at most {}", file.entries.len(), HIGH_SCORE_ENTRIES);
+        }
+        Ok(file.entries)
+    }
+    // Writes the table out, if it has somewhere to go.
+    pub fn save(&self) -> Result<(), anyhow::Error> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let file = HighScoreFile {
+            version: HIGH_SCORE_VERSION,
+            checksum: checksum(&self.entries),
+            entries: self.entries.clone(),
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let text = ron::ser::to_string_pretty(&file, ron::ser::PrettyConfig::default())?;
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+    // Whether `score` would make it into the table.
+    pub fn qualifies(&self, score: u32) -> bool {
+        score > 0 && (self.entries.len() < HIGH_SCORE_ENTRIES ||
+            self.entries.last().map_or(true, |last| score > last.score))
+    }
+    // Puts `entry` in its place, below any equal score already there.
+    pub fn insert(&mut self, entry: HighScore) {
+        let at = self.entries.iter()
+            .position(|other| entry.score > other.score)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(at, entry);
+        self.entries.truncate(HIGH_SCORE_ENTRIES);
+    }
+    pub fn best(&self) -> u32 {
+        self.entries.first().map_or(0, |best| best.score)
+    }
+    // The user's data directory, as each platform has it. There is none on
+    // the web.
+    pub fn default_path() -> Option<PathBuf> {
+        if cfg!(target_arch = "wasm32") {
+            return None;
+        }
+        let home = || std::env::var_os("HOME").map(PathBuf::from);
+        let data_dir = if cfg!(target_os = "windows") {
+            std::env::var_os("APPDATA").map(PathBuf::from)
+        } else if cfg!(target_os = "macos") {
+            home().map(|home| home.join("Library").join("Application Support"))
+        } else {
+            std::env::var_os("XDG_DATA_HOME")
+                .map(PathBuf::from)
+                .or_else(|| home().map(|home| home.join(".local").join("share")))
+        };
+        data_dir.map(|dir| dir.join("pacman").join("high_scores.ron"))
+    }
+}
+
+// FNV-1a, which unlike the standard hasher is the same on every build.
+fn checksum(entries: &[HighScore]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for entry in entries {
+        let bytes = entry.initials.bytes()
+            .chain(entry.score.to_le_bytes().iter().copied())
+            .chain(entry.level.to_le_bytes().iter().copied());
+        for byte in bytes {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+// The initials being typed in after a game good enough for the table.
+#[derive(Default)]
+pub struct InitialsEntry {
+    pub active: bool,
+    pub initials: String,
+}
+
+// Loads the table from `GameConfig::high_scores`, and asks for initials at
+// the end of a game that makes it in, when there is a keyboard to type them.
+// The `Score` it checks is the one `ScoringPlugin` keeps, whichever of the
+// two is added first.
+pub struct HighScorePlugin;
+
+impl Plugin for HighScorePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        game::add_core(app);
+        let path = app.resources().get::<GameConfig>().unwrap().high_scores.clone();
+        let table = HighScores::load(path);
+        if !app.resources().contains::<Score>() {
+            app.add_resource(Score::default());
+        }
+        app.resources().get_mut::<Score>().unwrap().high = table.best();
+        app.add_resource(table)
+            .add_resource(InitialsEntry::default());
+        if app.resources().contains::<Input<KeyCode>>() &&
+            app.resources().contains::<Events<ReceivedCharacter>>() {
+            app.on_game_state_enter(GameState::GameOver, initials_start.system())
+                .add_system(initials_keys.system());
+        }
+    }
+}
+
+fn initials_start(
+    score: Res<Score>,
+    table: Res<HighScores>,
+    mut entry: ResMut<InitialsEntry>,
+) {
+    if table.qualifies(score.current) {
+        entry.active = true;
+        entry.initials.clear();
+    }
+}
+
+// Letters fill in the initials, backspace takes one back, and enter puts
+// them in the table once all three are there.
+fn initials_keys(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut char_reader: Local<EventReader<ReceivedCharacter>>,
+    chars: Res<Events<ReceivedCharacter>>,
+    score: Res<Score>,
+    level: Res<Level>,
+    mut table: ResMut<HighScores>,
+    mut entry: ResMut<InitialsEntry>,
+) {
+    let typed: Vec<char> = char_reader.iter(&chars).map(|typed| typed.char).collect();
+    if !entry.active {
+        return;
+    }
+    for c in typed {
+        if c.is_ascii_alphabetic() && entry.initials.len() < 3 {
+            entry.initials.push(c.to_ascii_uppercase());
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        entry.initials.pop();
+    }
+    if keyboard_input.just_pressed(KeyCode::Return) && entry.initials.len() == 3 {
+        entry.active = false;
+        table.insert(HighScore{initials: entry.initials.clone(), score: score.current, level: level.0});
+        if let Err(err) = table.save() {
+            error!("Couldn't save high scores: {}", err);
+        }
+    }
+}
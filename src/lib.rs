@@ -0,0 +1,39 @@
+// Pac-Man as a set of bevy plugins. `PacmanGamePlugin` plays the whole game
+// and runs fine without a window, `PacmanGraphicsPlugin` draws it on top.
+// The pieces the game is made of can be added on their own too:
+//
+//   `MazePlugin`      loads the maze into the `Maze` resource, and lays out
+//                     its walls and dots
+//   `PacmanPlugin`    Pac-Man, eating the dots, and the player's input
+//   `GhostAiPlugin`   the ghosts, their modes, and running into Pac-Man
+//   `FruitPlugin`     bonus fruit
+//   `ScoringPlugin`   the score
+//   `HighScorePlugin` the high score table, and initials for it
+//
+// `SoundPlugin` plays the game's sounds, built with the `audio` feature.
+//
+// They share the `GAME_STAGE` and the state in `game`, set up by whichever is
+// added first, and read the `GameConfig` resource when they are built.
+
+pub mod fruit;
+pub mod game;
+pub mod ghost;
+pub mod graphics;
+pub mod high_scores;
+pub mod maze;
+pub mod pacman;
+pub mod replay;
+pub mod scoring;
+#[cfg(feature = "audio")]
+pub mod sound;
+
+pub use fruit::FruitPlugin;
+pub use game::{GameConfig, GameStageExt, PacmanGamePlugin};
+pub use ghost::GhostAiPlugin;
+pub use graphics::PacmanGraphicsPlugin;
+pub use high_scores::HighScorePlugin;
+pub use maze::MazePlugin;
+pub use pacman::PacmanPlugin;
+pub use scoring::ScoringPlugin;
+#[cfg(feature = "audio")]
+pub use sound::SoundPlugin;
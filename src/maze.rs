@@ -0,0 +1,761 @@
+use bevy::{
+    prelude::*,
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    ecs::State,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use rand::{rngs::StdRng, seq::SliceRandom};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::path::PathBuf;
+
+use crate::game::{self, game_stage, GameConfig, GameStageExt, GameState};
+use crate::pacman::{Energy, Food};
+use crate::ghost::GhostKind;
+
+#[derive(Default, Copy, Clone, Eq, PartialEq, Hash, Debug)] 
+pub struct Position {
+    pub x: i32,
+    pub y: i32,
+}
+
+impl Position {
+    pub fn euclid_distance(self, x:i32, y:i32) -> f32 {
+        (((self.y-y).pow(2) + (self.x-x).pow(2)) as f32).sqrt()
+    }
+    // The tile `tiles` steps away in `direction`, which may lie off the maze.
+    pub fn ahead(self, direction: Direction, tiles: i32) -> Position {
+        match direction {
+            Direction::Left => Position{x: self.x - tiles, y: self.y},
+            Direction::Up => Position{x: self.x, y: self.y - tiles},
+            Direction::Right => Position{x: self.x + tiles, y: self.y},
+            Direction::Down => Position{x: self.x, y: self.y + tiles},
+        }
+    }
+    // Tiles a ghost heading in `direction` may step onto next, in order of
+    // preference when distances tie. Ghosts never turn back on themselves.
+    pub fn ghost_moves(self, maze: &Maze, direction: Direction, actor: Actor) -> Vec<(Position, Direction)> {
+        maze.neighbors(self)
+            .into_iter()
+            .filter(|&(next, next_dir)| {
+                next_dir != direction.opposite() &&
+                    maze.can_move(actor, self, next_dir) &&
+                    maze.is_walkable_for(actor, next)
+            })
+            .collect()
+    }
+    // Where Pac-Man ends up stepping in `direction`, unless the way is
+    // blocked.
+    pub fn pacman_step(self, maze: &Maze, direction: Direction) -> Option<Position> {
+        let next = maze.step(self, direction);
+        if maze.is_walkable_for(Actor::Pacman, next) {
+            Some(next)
+        } else {
+            None
+        }
+    }
+    pub fn in_ghost_house(self, maze: &Maze) -> bool {
+        matches!(maze.tile_at(self), Tile::Void | Tile::Gate)
+    }
+    pub fn choose_next_tile(self, maze: &Maze, direction: Direction, target: Position, actor: Actor) -> (Position, Direction){
+  	let mut tile: Position = self;
+	let mut shortest: f32 = 99999.;
+        let mut dir: Direction = direction;
+
+        for (next, next_dir) in self.ghost_moves(maze, direction, actor) {
+            let distance = target.euclid_distance(next.x, next.y);
+            if distance < shortest {
+                shortest = distance;
+                tile = next;
+                dir = next_dir;
+            }
+        }
+        (tile, dir)
+    }
+    // Frightened ghosts pick any turn they are allowed to take at random.
+    pub fn choose_random_tile(self, maze: &Maze, direction: Direction, rng: &mut StdRng) -> (Position, Direction){
+        *self.ghost_moves(maze, direction, Actor::Ghost)
+            .choose(rng)
+            .unwrap_or(&(self, direction))
+    }
+    // Like `choose_next_tile`, but follows the shortest way through the maze
+    // instead of heading straight for the target.
+    pub fn choose_route_tile(self, maze: &Maze, direction: Direction, target: Position, actor: Actor) -> (Position, Direction){
+        self.ghost_moves(maze, direction, actor)
+            .into_iter()
+            .min_by_key(|&(next, _)| maze.distance(actor, next, target).unwrap_or(u32::MAX))
+            .unwrap_or((self, direction))
+    }
+ 
+}
+
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum Direction {
+    Left,
+    Up,
+    Right,
+    Down,
+}
+
+impl Direction {
+    pub fn opposite(self) -> Self {
+        match self {
+            Self::Left => Self::Right,
+            Self::Right => Self::Left,
+            Self::Up => Self::Down,
+            Self::Down => Self::Up,
+        }
+    }
+    pub fn quarter_cw(self) -> Self {
+        match self {
+            Self::Left => Self::Up,
+            Self::Right => Self::Down,
+            Self::Up => Self::Right,
+            Self::Down => Self::Left,
+        }
+    }
+    pub fn quarter_ccw(self) -> Self {
+        match self {
+            Self::Left => Self::Down,
+            Self::Right => Self::Up,
+            Self::Up => Self::Left,
+            Self::Down => Self::Right,
+        }
+    }
+}
+
+// Where an actor starts out, and is put back after Pac-Man loses a life.
+#[derive(Copy, Clone)]
+pub struct Spawn {
+    pub position: Position,
+    pub direction: Direction,
+}
+
+// Actors slide from tile to tile instead of jumping. The game logic only
+// ever sees `Position`, this is how far the actor has come into it from
+// `from`, and how fast it goes in tiles per second.
+pub struct Motion {
+    pub from: Position,
+    pub progress: f32,
+    pub speed: f32,
+}
+
+impl Motion {
+    pub fn at(position: Position) -> Self {
+        Self {from: position, progress: 0., speed: 0.}
+    }
+}
+
+pub const MAZE_PATH: &str = "mazes/classic.maze";
+
+// Walls and the ghost house gate, put on the maze so there is something to
+// draw them with. Nothing in the game looks at them.
+pub struct Wall;
+
+pub struct HouseGate;
+
+// Fills in the `Maze` resource with `GameConfig::maze`, from the asset server
+// when there is one and straight off the disk otherwise, lays out its walls
+// and dots, and clears the level once the dots are gone. A maze already put
+// in the resource before the plugin is added is used as it is.
+pub struct MazePlugin;
+
+impl Plugin for MazePlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        game::add_core(app);
+        if app.resources().contains::<AssetServer>() {
+            app.add_asset::<Maze>()
+                .init_asset_loader::<MazeLoader>()
+                .add_system(maze_load.system());
+        } else {
+            app.add_startup_system(maze_read.system());
+        }
+        app.add_system(maze_setup.system())
+            .add_game_system(game_stage::MODE, level_clear_check.system())
+            .on_game_state_exit(GameState::LevelClear, dots_refill.system())
+            .on_game_state_exit(GameState::Title, dots_refill.system());
+    }
+}
+
+// Puts the walls, the gate and the dots on a freshly loaded maze. These carry
+// everything the game logic needs, sprites are added on top when there is
+// something to draw them on.
+fn maze_setup(
+    commands: &mut Commands,
+    maze: Res<Maze>,
+    mut spawned: Local<bool>,
+) {
+    if *spawned || maze.is_empty() {
+        return;
+    }
+    *spawned = true;
+    for j in 0..maze.height {
+        for i in 0..maze.width {
+            let tile = maze.tile_at(Position{x:i, y:j});
+            if tile == Tile::Wall {
+                commands.spawn((Wall, Position{x:i, y:j}));
+            } else if tile == Tile::Gate {
+                commands.spawn((HouseGate, Position{x:i, y:j}));
+            }
+        }
+    }
+    spawn_dots(commands, &maze);
+}
+
+// A full set of dots for each new level and game.
+fn dots_refill(
+    commands: &mut Commands,
+    maze: Res<Maze>,
+    dots: Query<Entity, Or<(With<Food>, With<Energy>)>>,
+) {
+    for entity in dots.iter() {
+        commands.despawn(entity);
+    }
+    spawn_dots(commands, &maze);
+}
+
+fn level_clear_check(
+    maze: Res<Maze>,
+    mut state: ResMut<State<GameState>>,
+    dots: Query<Entity, Or<(With<Food>, With<Energy>)>>,
+) {
+    // nothing to clear until the maze has loaded, and being caught on the
+    // same tick comes first
+    if maze.is_empty() || state.next().is_some() {
+        return;
+    }
+    if dots.iter().next().is_none() {
+        state.set_next(GameState::LevelClear).unwrap();
+    }
+}
+
+fn spawn_dots(commands: &mut Commands, maze: &Maze) {
+    for j in 0..maze.height {
+        for i in 0..maze.width {
+            let tile = maze.tile_at(Position{x:i, y:j});
+            if tile.has_dot() {
+                commands.spawn((Food{}, Position{x:i, y:j}));
+            } else if tile == Tile::Energizer {
+                commands.spawn((Energy{}, Position{x:i, y:j}));
+            }
+        }
+    }
+}
+
+// The maze file to load, relative to the assets directory unless absolute.
+fn maze_path(config: &GameConfig) -> PathBuf {
+    config.maze.clone().unwrap_or_else(|| PathBuf::from(MAZE_PATH))
+}
+
+fn maze_load(
+    config: Res<GameConfig>,
+    asset_server: Res<AssetServer>,
+    mazes: Res<Assets<Maze>>,
+    mut maze: ResMut<Maze>,
+    mut handle: Local<Option<Handle<Maze>>>,
+) {
+    if !maze.is_empty() {
+        return;
+    }
+    let handle = handle.get_or_insert_with(|| asset_server.load(maze_path(&config).as_path()));
+    if let Some(loaded) = mazes.get(&*handle) {
+        *maze = loaded.clone();
+    }
+}
+
+fn maze_read(config: Res<GameConfig>, mut maze: ResMut<Maze>) {
+    if !maze.is_empty() {
+        return;
+    }
+    let path = assets_dir().join(maze_path(&config));
+    let source = std::fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+    *maze = Maze::parse(&source)
+        .unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+}
+
+// Where the assets are, found the way bevy's asset server finds them: next
+// to the manifest under cargo, and next to the executable otherwise.
+fn assets_dir() -> PathBuf {
+    let root = match std::env::var_os("CARGO_MANIFEST_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => std::env::current_exe()
+            .ok()
+            .and_then(|exe| exe.parent().map(PathBuf::from))
+            .unwrap_or_default(),
+    };
+    root.join("assets")
+}
+
+// The layout of a level, read from a `.maze` text file in `assets/mazes`.
+// Each character is one tile:
+//
+//   `#` wall            `.` dot             `o` energizer
+//   `-` ghost house gate                    `_` dot ghosts can't turn up from
+//   ` ` filler, and inside the house, rows shorter than the widest one are
+//       filled out with it
+//   `P` Pac-Man's spawn tile, and `b`, `p`, `i`, `c` where Blinky, Pinky,
+//   Inky and Clyde start out
+//   `F` where bonus fruit shows up, a maze without one has no fruit
+//
+// Walking off an open edge tile comes back in on the opposite edge, and the
+// walled-in corridors leading there are tunnels that slow the ghosts down.
+#[derive(Debug, Clone, Default, TypeUuid)]
+#[uuid = "6f3b7c2e-4d2a-4b8e-9a51-1c7d0e5f8a34"]
+pub struct Maze {
+    pub width: i32,
+    pub height: i32,
+    pub tiles: Vec<Tile>,
+    // tiles where ghosts slow down, worked out from the layout
+    pub tunnels: Vec<bool>,
+    pub pacman_spawn: Position,
+    // indexed by `GhostKind`
+    pub ghost_spawns: [Position; 4],
+    pub fruit_spawn: Option<Position>,
+    // the tile right above the gate, where ghosts leave the house
+    pub house_entrance: Position,
+    // the tile right below the gate, where eaten ghosts are revived
+    pub house_center: Position,
+    // indexed by `Actor`, as each one can go different places
+    pub navigation: [NavGraph; 3],
+    // the text it was read from, which goes into replays
+    pub source: String,
+}
+
+impl Maze {
+    pub fn parse(source: &str) -> Result<Maze, MazeError> {
+        let rows: Vec<&str> = source.lines()
+            .map(|line| line.trim_end_matches('\r'))
+            .collect();
+        let rows: Vec<&str> = match rows.iter().rposition(|row| !row.trim().is_empty()) {
+            Some(last) => rows[..=last].to_vec(),
+            None => return Err(MazeError::new("the maze is empty")),
+        };
+        let width = rows.iter().map(|row| row.chars().count()).max().unwrap();
+        let mut tiles = Vec::with_capacity(width * rows.len());
+        let mut pacman_spawn = None;
+        let mut ghost_spawns: [Option<Position>; 4] = [None; 4];
+        let mut fruit_spawn = None;
+        let mut gates = Vec::new();
+        for (y, row) in rows.iter().enumerate() {
+            for (x, c) in row.chars().enumerate() {
+                let position = Position{x: x as i32, y: y as i32};
+                let marker = match c {
+                    'P' => Some(&mut pacman_spawn),
+                    'b' => Some(&mut ghost_spawns[GhostKind::Blinky as usize]),
+                    'p' => Some(&mut ghost_spawns[GhostKind::Pinky as usize]),
+                    'i' => Some(&mut ghost_spawns[GhostKind::Inky as usize]),
+                    'c' => Some(&mut ghost_spawns[GhostKind::Clyde as usize]),
+                    'F' => Some(&mut fruit_spawn),
+                    _ => None,
+                };
+                if let Some(marker) = marker {
+                    if marker.is_some() {
+                        return Err(MazeError::at(y + 1, x + 1, &format!("'{}' appears more than once", c)));
+                    }
+                    *marker = Some(position);
+                }
+                tiles.push(match c {
+                    '.' => Tile::Dot,
+                    '#' => Tile::Wall,
+                    'o' => Tile::Energizer,
+                    '-' => { gates.push(position); Tile::Gate }
+                    '_' => Tile::NoUp,
+                    'P' | 'b' | 'F' => Tile::Path,
+                    ' ' | 'p' | 'i' | 'c' => Tile::Void,
+                    _ => return Err(MazeError::at(y + 1, x + 1, &format!("unknown tile '{}'", c))),
+                });
+            }
+            // filler at the end of a row may have been trimmed off with the
+            // trailing whitespace
+            tiles.extend(std::iter::repeat(Tile::Void).take(width - row.chars().count()));
+        }
+
+        let pacman_spawn = pacman_spawn
+            .ok_or_else(|| MazeError::new("no spawn tile 'P' for Pac-Man"))?;
+        let mut spawns = [Position::default(); 4];
+        for (spawn, (marker, name)) in spawns.iter_mut().zip(ghost_spawns.iter().zip(["b", "p", "i", "c"].iter())) {
+            *spawn = marker.ok_or_else(|| MazeError::new(&format!("no spawn tile '{}' for a ghost", name)))?;
+        }
+        let top = gates.iter().min_by_key(|gate| gate.y)
+            .ok_or_else(|| MazeError::new("no ghost house gate '-'"))?;
+        let bottom = gates.iter().max_by_key(|gate| gate.y).unwrap();
+        let mut maze = Maze {
+            width: width as i32,
+            height: rows.len() as i32,
+            tiles,
+            tunnels: Vec::new(),
+            pacman_spawn,
+            ghost_spawns: spawns,
+            fruit_spawn,
+            house_entrance: Position{x: top.x, y: top.y - 1},
+            house_center: Position{x: bottom.x, y: bottom.y + 1},
+            navigation: Default::default(),
+            source: source.to_string(),
+        };
+        maze.tunnels = maze.find_tunnels();
+        for &actor in [Actor::Pacman, Actor::Ghost, Actor::HouseGhost].iter() {
+            maze.navigation[actor as usize] = NavGraph::build(&maze, actor);
+        }
+        Ok(maze)
+    }
+    // A tunnel starts at an open edge tile that wraps around to an open tile
+    // on the opposite edge, and runs inwards for as long as it is walled in
+    // on both sides.
+    fn find_tunnels(&self) -> Vec<bool> {
+        let mut tunnels = vec![false; self.tiles.len()];
+        let open = |pos: Position| !matches!(self.tile_at(pos), Tile::Wall | Tile::Void);
+        let mut entrances = Vec::new();
+        for y in 0..self.height {
+            entrances.push((Position{x: 0, y}, Direction::Right));
+            entrances.push((Position{x: self.width - 1, y}, Direction::Left));
+        }
+        for x in 0..self.width {
+            entrances.push((Position{x, y: 0}, Direction::Down));
+            entrances.push((Position{x, y: self.height - 1}, Direction::Up));
+        }
+        for (mut pos, inwards) in entrances {
+            if !open(pos) || !open(self.step(pos, inwards.opposite())) {
+                continue;
+            }
+            while self.contains(pos) &&
+                open(pos) &&
+                !open(pos.ahead(inwards.quarter_cw(), 1)) &&
+                !open(pos.ahead(inwards.quarter_ccw(), 1)) {
+                tunnels[(pos.y * self.width + pos.x) as usize] = true;
+                pos = pos.ahead(inwards, 1);
+            }
+        }
+        tunnels
+    }
+    // Off the maze counts as wall.
+    pub fn tile_at(&self, pos: Position) -> Tile {
+        if self.contains(pos) {
+            self.tiles[(pos.y * self.width + pos.x) as usize]
+        } else {
+            Tile::Wall
+        }
+    }
+    pub fn is_tunnel(&self, pos: Position) -> bool {
+        self.contains(pos) && self.tunnels[(pos.y * self.width + pos.x) as usize]
+    }
+    pub fn contains(&self, pos: Position) -> bool {
+        pos.x >= 0 && pos.y >= 0 && pos.x < self.width && pos.y < self.height
+    }
+    // The tile next to `pos` in `direction`. Stepping off one edge of the
+    // maze comes back in on the opposite edge.
+    pub fn step(&self, pos: Position, direction: Direction) -> Position {
+        let next = pos.ahead(direction, 1);
+        if self.is_empty() {
+            return next;
+        }
+        Position{x: next.x.rem_euclid(self.width), y: next.y.rem_euclid(self.height)}
+    }
+    // The four tiles around `pos`, in the order ghosts prefer to turn when
+    // two ways are equally good.
+    pub fn neighbors(&self, pos: Position) -> Vec<(Position, Direction)> {
+        [Direction::Up, Direction::Left, Direction::Down, Direction::Right]
+            .iter()
+            .map(|&direction| (self.step(pos, direction), direction))
+            .collect()
+    }
+    pub fn is_walkable_for(&self, actor: Actor, pos: Position) -> bool {
+        match actor {
+            Actor::Pacman | Actor::Ghost => !matches!(self.tile_at(pos), Tile::Wall | Tile::Gate | Tile::Void),
+            Actor::HouseGhost => self.tile_at(pos) != Tile::Wall,
+        }
+    }
+    // Whether `actor` may leave `pos` in `direction`, going by `TURN_RULES`.
+    pub fn can_move(&self, actor: Actor, pos: Position, direction: Direction) -> bool {
+        let tile = self.tile_at(pos);
+        !TURN_RULES.iter()
+            .any(|rule| rule.tile == tile && rule.actor == actor && rule.direction == direction)
+    }
+    // Length of the shortest way from `from` to `to` for `actor`, if there is
+    // one at all.
+    pub fn distance(&self, actor: Actor, from: Position, to: Position) -> Option<u32> {
+        self.navigation[actor as usize].distance(self, actor, from, to)
+    }
+    // The tile to step onto from `from` to get to `to` the shortest way.
+    pub fn next_step(&self, actor: Actor, from: Position, to: Position) -> Option<Position> {
+        self.navigation[actor as usize].next_step(self, actor, from, to)
+    }
+    // Every tile along the shortest way from `from` to `to`, `to` included.
+    pub fn path(&self, actor: Actor, from: Position, to: Position) -> Option<Vec<Position>> {
+        self.navigation[actor as usize].path(self, actor, from, to)
+    }
+    // True until the maze file has been loaded.
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+    // Each ghost heads for its own corner in scatter mode.
+    pub fn scatter_target(&self, kind: GhostKind) -> Position {
+        match kind {
+            GhostKind::Blinky => Position{x: 1, y: 1},
+            GhostKind::Pinky => Position{x: self.width - 2, y: 1},
+            GhostKind::Inky => Position{x: 1, y: self.height - 2},
+            GhostKind::Clyde => Position{x: self.width - 2, y: self.height - 2},
+        }
+    }
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Tile {
+    Wall,
+    Dot,
+    Energizer,
+    Gate,
+    // a dot ghosts may not turn up from
+    NoUp,
+    // open floor without a dot
+    Path,
+    // filler outside the corridors, and the inside of the ghost house
+    Void,
+}
+
+impl Tile {
+    pub fn has_dot(self) -> bool {
+        matches!(self, Tile::Dot | Tile::NoUp)
+    }
+}
+
+// Who is asking whether a tile can be walked on. Only Pac-Man and ghosts
+// out in the maze are kept out of the ghost house.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Actor {
+    Pacman,
+    Ghost,
+    // eyes on their way back in, and ghosts being let out
+    HouseGhost,
+}
+
+// A way `actor` may not go when leaving `tile`.
+pub struct TurnRule {
+    pub tile: Tile,
+    pub actor: Actor,
+    pub direction: Direction,
+}
+
+pub const TURN_RULES: &[TurnRule] = &[
+    // as in the arcade, ghosts can't turn up on the corridors running past
+    // the house door and past Pac-Man's start
+    TurnRule{tile: Tile::NoUp, actor: Actor::Ghost, direction: Direction::Up},
+];
+
+// The maze boiled down to its junctions, and the corridors joining them, as
+// far as one kind of actor is concerned. Worked out once when the maze is
+// loaded.
+#[derive(Debug, Clone, Default)]
+pub struct NavGraph {
+    pub edges: HashMap<Position, Vec<NavEdge>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NavEdge {
+    pub to: Position,
+    pub length: u32,
+}
+
+impl NavGraph {
+    pub fn build(maze: &Maze, actor: Actor) -> Self {
+        let mut edges = HashMap::new();
+        for y in 0..maze.height {
+            for x in 0..maze.width {
+                let pos = Position{x, y};
+                if !Self::is_node(maze, actor, pos) {
+                    continue;
+                }
+                let corridors = Self::exits(maze, actor, pos)
+                    .into_iter()
+                    .filter_map(|direction| Self::walk(maze, actor, pos, direction, None))
+                    .map(|(to, length, _)| NavEdge{to, length})
+                    .collect();
+                edges.insert(pos, corridors);
+            }
+        }
+        Self {edges}
+    }
+    fn exits(maze: &Maze, actor: Actor, pos: Position) -> Vec<Direction> {
+        maze.neighbors(pos)
+            .into_iter()
+            .filter(|&(next, _)| maze.is_walkable_for(actor, next))
+            .map(|(_, direction)| direction)
+            .collect()
+    }
+    // Anywhere the way splits, turns back or comes to a dead end.
+    fn is_node(maze: &Maze, actor: Actor, pos: Position) -> bool {
+        maze.is_walkable_for(actor, pos) && Self::exits(maze, actor, pos).len() != 2
+    }
+    // Follows the corridor leaving `from` in `direction` up to the next node,
+    // returning where it ends, how long it is, and how far along it `target`
+    // was passed, if it was.
+    fn walk(maze: &Maze, actor: Actor, from: Position, direction: Direction, target: Option<Position>) -> Option<(Position, u32, Option<u32>)> {
+        let mut pos = from;
+        let mut direction = direction;
+        let mut length = 0;
+        let mut passed = None;
+        loop {
+            pos = maze.step(pos, direction);
+            length += 1;
+            if passed.is_none() && Some(pos) == target {
+                passed = Some(length);
+            }
+            if pos == from || Self::is_node(maze, actor, pos) {
+                return Some((pos, length, passed));
+            }
+            if length as usize > maze.tiles.len() {
+                return None;
+            }
+            direction = *Self::exits(maze, actor, pos)
+                .iter()
+                .find(|&&exit| exit != direction.opposite())?;
+        }
+    }
+    // The nodes closest to `pos` either way along its corridor, or `pos`
+    // itself when it is a node.
+    fn anchors(maze: &Maze, actor: Actor, pos: Position, target: Option<Position>) -> (Vec<(Position, u32)>, Option<u32>) {
+        if Self::is_node(maze, actor, pos) {
+            return (vec![(pos, 0)], None);
+        }
+        let mut anchors = Vec::new();
+        let mut direct: Option<u32> = None;
+        for direction in Self::exits(maze, actor, pos) {
+            if let Some((node, length, passed)) = Self::walk(maze, actor, pos, direction, target) {
+                anchors.push((node, length));
+                direct = match (direct, passed) {
+                    (Some(a), Some(b)) => Some(a.min(b)),
+                    (a, b) => a.or(b),
+                };
+            }
+        }
+        (anchors, direct)
+    }
+    // Shortest way from `from` to `to`, found with A* over the junctions.
+    pub fn distance(&self, maze: &Maze, actor: Actor, from: Position, to: Position) -> Option<u32> {
+        if from == to {
+            return Some(0);
+        }
+        if !maze.is_walkable_for(actor, from) || !maze.is_walkable_for(actor, to) {
+            return None;
+        }
+        let (starts, direct) = Self::anchors(maze, actor, from, Some(to));
+        let (goals, _) = Self::anchors(maze, actor, to, None);
+        // never overestimates, even through the tunnels
+        let estimate = |pos: Position| {
+            goals.iter()
+                .map(|&(goal, length)| {
+                    let dx = (pos.x - goal.x).abs();
+                    let dy = (pos.y - goal.y).abs();
+                    (dx.min(maze.width - dx) + dy.min(maze.height - dy)) as u32 + length
+                })
+                .min()
+                .unwrap_or(0)
+        };
+
+        let mut best = direct;
+        let mut costs: HashMap<Position, u32> = HashMap::new();
+        let mut open = BinaryHeap::new();
+        for &(node, length) in starts.iter() {
+            if costs.get(&node).map_or(true, |&cost| length < cost) {
+                costs.insert(node, length);
+                open.push(Reverse((length + estimate(node), length, node.x, node.y)));
+            }
+        }
+        while let Some(Reverse((estimated, cost, x, y))) = open.pop() {
+            if best.map_or(false, |best| estimated >= best) {
+                break;
+            }
+            let node = Position{x, y};
+            if costs.get(&node).map_or(false, |&known| cost > known) {
+                continue;
+            }
+            for &(goal, length) in goals.iter() {
+                if goal == node && best.map_or(true, |best| cost + length < best) {
+                    best = Some(cost + length);
+                }
+            }
+            for edge in self.edges.get(&node).into_iter().flatten() {
+                let next_cost = cost + edge.length;
+                if costs.get(&edge.to).map_or(true, |&known| next_cost < known) {
+                    costs.insert(edge.to, next_cost);
+                    open.push(Reverse((next_cost + estimate(edge.to), next_cost, edge.to.x, edge.to.y)));
+                }
+            }
+        }
+        best
+    }
+    // Of the tiles next to `from`, the first one that is a step closer to
+    // `to`, in the order `Maze::neighbors` gives them. None once there.
+    pub fn next_step(&self, maze: &Maze, actor: Actor, from: Position, to: Position) -> Option<Position> {
+        let distance = self.distance(maze, actor, from, to)?;
+        if distance == 0 {
+            return None;
+        }
+        Self::exits(maze, actor, from)
+            .into_iter()
+            .map(|direction| maze.step(from, direction))
+            .find(|&next| self.distance(maze, actor, next, to) == Some(distance - 1))
+    }
+    pub fn path(&self, maze: &Maze, actor: Actor, from: Position, to: Position) -> Option<Vec<Position>> {
+        let distance = self.distance(maze, actor, from, to)?;
+        let mut path = Vec::with_capacity(distance as usize);
+        let mut pos = from;
+        while pos != to {
+            pos = self.next_step(maze, actor, pos, to)?;
+            path.push(pos);
+        }
+        Some(path)
+    }
+}
+
+#[derive(Debug)]
+pub struct MazeError {
+    // the line and column of the tile at fault, counting from 1, unless it
+    // is the maze as a whole that is wrong
+    pub position: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl MazeError {
+    pub fn new(message: &str) -> Self {
+        Self {position: None, message: message.to_string()}
+    }
+    pub fn at(line: usize, column: usize, message: &str) -> Self {
+        Self {position: Some((line, column)), message: message.to_string()}
+    }
+}
+
+impl std::fmt::Display for MazeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.position {
+            Some((line, column)) => write!(f, "line {}, column {}: {}", line, column, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for MazeError {}
+
+#[derive(Default)]
+pub struct MazeLoader;
+
+impl AssetLoader for MazeLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let maze = Maze::parse(std::str::from_utf8(bytes)?)
+                .map_err(|err| anyhow::anyhow!("{}: {}", load_context.path().display(), err))?;
+            load_context.set_default_asset(LoadedAsset::new(maze));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["maze"]
+    }
+}
@@ -0,0 +1,228 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::game::{self, game_stage, Difficulty, Game, GameConfig, GameStageExt, GameState, TICK_SECONDS};
+use crate::ghost::{FrightenedTimer, Ghost, GhostState};
+use crate::maze::{Direction, Maze, Motion, Position, Spawn};
+use crate::scoring::{ScoreEvent, ScoreReason, ENERGY_POINTS, FOOD_POINTS};
+
+pub struct Pacman {
+    pub direction: Direction,
+    // a turn asked for before Pac-Man reached a tile where it can be taken
+    pub buffered: Option<Direction>,
+    pub last: Position,
+}
+
+impl Pacman {
+    // Whether Pac-Man is eating whatever lies on `pos`. Things are eaten off
+    // the tile it has just stepped away from.
+    pub fn eats(&self, pos: Position) -> bool {
+        pos == self.last
+    }
+}
+
+pub struct Food {}
+
+pub struct Energy {}
+
+// Where the player wants Pac-Man to go: a direction asked for since the last
+// tick, and one still being asked for now. It comes from the keyboard, read
+// every frame, or from a replay, and is only acted on when the game ticks.
+#[derive(Default, Copy, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct PlayerIntent {
+    pub pressed: Option<Direction>,
+    pub held: Option<Direction>,
+}
+
+// Pac-Man and the dots it eats, which `MazePlugin` lays out. The keyboard
+// steers it when there is one and no replay is playing, otherwise
+// `PlayerIntent` has to be set from outside.
+pub struct PacmanPlugin;
+
+impl Plugin for PacmanPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        game::add_core(app);
+        let replaying = app.resources().get::<GameConfig>().unwrap().replay.is_some();
+        if app.resources().contains::<Input<KeyCode>>() && !replaying {
+            app.add_system(keyboard_input.system());
+        }
+        app.add_resource(PlayerIntent::default())
+            .add_system(pacman_setup.system())
+            .on_game_state_enter(GameState::Ready, pacman_respawn.system())
+            .add_game_system(game_stage::INPUT, pacman_input.system())
+            .add_game_system(game_stage::MOVEMENT, pacman_speed.system())
+            .add_game_system(game_stage::MOVEMENT, pacman_movement.system())
+            .add_game_system(game_stage::EATING, pacman_eating.system())
+            .add_game_system(game_stage::EATING, pacman_energy_boost.system());
+    }
+}
+
+// Puts Pac-Man on its spawn tile once there is a maze to put it on.
+fn pacman_setup(
+    commands: &mut Commands,
+    maze: Res<Maze>,
+    mut spawned: Local<bool>,
+) {
+    if *spawned || maze.is_empty() {
+        return;
+    }
+    *spawned = true;
+    let position = maze.pacman_spawn;
+    commands.spawn((
+        Pacman{direction: Direction::Right, buffered: None, last: position},
+        position,
+        Spawn{position, direction: Direction::Right},
+        Motion::at(position),
+    ));
+}
+
+// Back on the spawn tile for every new life and level.
+fn pacman_respawn(
+    mut pacmans: Query<(Entity, &mut Pacman, &Spawn)>,
+    mut positions: Query<&mut Position>,
+    mut motions: Query<&mut Motion>,
+) {
+    for (entity, mut pacman, spawn) in pacmans.iter_mut() {
+        let mut pos = positions.get_mut(entity).unwrap();
+        *pos = spawn.position;
+        *motions.get_mut(entity).unwrap() = Motion::at(spawn.position);
+        pacman.direction = spawn.direction;
+        pacman.buffered = None;
+        pacman.last = spawn.position;
+    }
+}
+
+fn keyboard_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut player_intent: ResMut<PlayerIntent>,
+) {
+    let keys = [
+        (KeyCode::Left, Direction::Left),
+        (KeyCode::Down, Direction::Down),
+        (KeyCode::Up, Direction::Up),
+        (KeyCode::Right, Direction::Right),
+    ];
+    if let Some((_, dir)) = keys.iter().find(|(key, _)| keyboard_input.just_pressed(*key)) {
+        player_intent.pressed = Some(*dir);
+    }
+    player_intent.held = keys.iter()
+        .find(|(key, _)| keyboard_input.pressed(*key))
+        .map(|(_, dir)| *dir);
+}
+
+// The last key pressed is remembered until Pac-Man can turn that way.
+fn pacman_input(
+    mut player_intent: ResMut<PlayerIntent>,
+    mut pacmans: Query<&mut Pacman>,
+) {
+    let pressed = player_intent.pressed.take();
+    for mut pacman in pacmans.iter_mut() {
+        if let Some(dir) = pressed {
+            pacman.buffered = Some(dir);
+        } else if let Some(dir) = player_intent.held {
+            if pacman.buffered.is_none() && dir != pacman.direction {
+                pacman.buffered = Some(dir);
+            }
+        }
+    }
+}
+
+fn pacman_speed(
+    game: Res<Game>,
+    difficulty: Res<Difficulty>,
+    mut pacmans: Query<&mut Motion, With<Pacman>>,
+) {
+    for mut motion in pacmans.iter_mut() {
+        motion.speed = if game.frightened {
+            difficulty.pacman_frightened_speed
+        } else {
+            difficulty.pacman_speed
+        };
+    }
+}
+
+fn pacman_movement(
+    maze: Res<Maze>,
+    mut pacmans: Query<(Entity, &mut Pacman, &mut Motion)>,
+    mut positions: Query<&mut Position>,
+) {
+    if let Some((entity, mut pacman, mut motion)) = pacmans.iter_mut().next() {
+        let mut pos = positions.get_mut(entity).unwrap();
+        // turning back doesn't wait for a tile, Pac-Man heads straight back
+        // the way it came
+        if pacman.buffered == Some(pacman.direction.opposite()) && motion.from != *pos {
+            pacman.direction = pacman.direction.opposite();
+            pacman.buffered = None;
+            let from = motion.from;
+            motion.from = *pos;
+            *pos = from;
+            motion.progress = 1. - motion.progress;
+        }
+        motion.progress += motion.speed * TICK_SECONDS;
+        if motion.progress < 1. {
+            return;
+        }
+
+        if let Some(dir) = pacman.buffered {
+            if pos.pacman_step(&maze, dir).is_some() {
+                pacman.direction = dir;
+                pacman.buffered = None;
+            }
+        }
+
+        pacman.last = *pos;
+        motion.from = *pos;
+        if let Some(next) = pos.pacman_step(&maze, pacman.direction) {
+            *pos = next;
+            motion.progress = (motion.progress - 1.).min(1.);
+        } else {
+            // up against a wall, ready to go as soon as a turn opens up
+            motion.progress = 1.;
+        }
+    }
+}
+
+fn pacman_eating(
+    commands: &mut Commands,
+    mut score_events: ResMut<Events<ScoreEvent>>,
+    foods: Query<(Entity, &Position), With<Food>>,
+    pacmans: Query<&Pacman>, 
+){
+    if let Some(pacman) = pacmans.iter().next() {
+        for (ent, food_pos) in foods.iter() {
+            if pacman.eats(*food_pos) {
+                commands.despawn(ent);
+                score_events.send(ScoreEvent{points: FOOD_POINTS, reason: ScoreReason::Food, position: *food_pos});
+            }
+        }
+    }
+}
+
+fn pacman_energy_boost(
+    commands: &mut Commands,
+    mut game: ResMut<Game>,
+    mut frightened_timer: ResMut<FrightenedTimer>,
+    mut score_events: ResMut<Events<ScoreEvent>>,
+    foods: Query<(Entity, &Position), With<Energy>>,
+    pacmans: Query<(Entity, &Pacman)>, 
+    mut ghosts: Query<&mut Ghost>,
+){
+    if let Some((_, pacman)) = pacmans.iter().next() {
+        for (ent, food_pos) in foods.iter() {
+            if pacman.eats(*food_pos) {
+                commands.despawn(ent);
+                score_events.send(ScoreEvent{points: ENERGY_POINTS, reason: ScoreReason::Energy, position: *food_pos});
+                game.frightened = true;
+                game.ghost_combo = 0;
+                frightened_timer.0.reset();
+                for mut ghost in ghosts.iter_mut() {
+                    if ghost.state == GhostState::Normal ||
+                        ghost.state == GhostState::Frightened {
+                        ghost.state = GhostState::Frightened;
+                        ghost.reverse = true;
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,213 @@
+use bevy::{prelude::*, app::AppExit, ecs::SystemParam};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+use crate::game::{Game, GameConfig, Level, Lives, Tick, TICK_SECONDS};
+use crate::ghost::Ghost;
+use crate::maze::{Maze, Motion, Position};
+use crate::pacman::{Energy, Food, Pacman, PlayerIntent};
+use crate::scoring::Score;
+
+pub const REPLAY_VERSION: u32 = 5;
+
+// how often a replay notes down the state of the game, in ticks
+pub const REPLAY_HASH_TICKS: u64 = 60;
+
+// Everything needed to play a game back exactly, from its first tick to game
+// over: the seed, the maze and the tick length it was played with, and what
+// the player wanted to do. The
+// intent is only written down on the ticks it changed. The state hashes are
+// there to tell when a playback has gone off the rails.
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct Replay {
+    pub version: u32,
+    pub seed: u64,
+    pub tick_seconds: f32,
+    pub maze: String,
+    pub intents: Vec<(u64, PlayerIntent)>,
+    pub hashes: Vec<(u64, u64)>,
+}
+
+impl Replay {
+    pub fn load(path: &Path) -> Result<Replay, anyhow::Error> {
+        let replay: Replay = ron::de::from_str(&std::fs::read_to_string(path)?)?;
+        if replay.version != REPLAY_VERSION {
+            anyhow::bail!("replay version {} can't be played, expected {}", replay.version, REPLAY_VERSION);
+        }
+        if replay.tick_seconds != TICK_SECONDS {
+            warn!("Replay was recorded with {}s ticks, playing back with {}s", replay.tick_seconds, TICK_SECONDS);
+        }
+        Ok(replay)
+    }
+    pub fn save(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let text = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        std::fs::write(path, text)?;
+        Ok(())
+    }
+    pub fn maze(&self) -> Maze {
+        Maze::parse(&self.maze)
+            .unwrap_or_else(|err| panic!("maze in replay: {}", err))
+    }
+}
+
+pub struct Playback {
+    pub replay: Replay,
+    pub next_intent: usize,
+    pub next_hash: usize,
+    pub desynced: bool,
+}
+
+// The replay being recorded, the one being played back, or both.
+pub struct ReplaySession {
+    pub record_to: Option<PathBuf>,
+    pub recording: Replay,
+    pub playing: Option<Playback>,
+}
+
+impl ReplaySession {
+    pub fn new(config: &GameConfig) -> Self {
+        Self {
+            record_to: config.record.clone(),
+            recording: Replay {
+                version: REPLAY_VERSION,
+                seed: config.seed,
+                tick_seconds: TICK_SECONDS,
+                ..Default::default()
+            },
+            playing: config.replay.clone().map(|replay| Playback {
+                replay,
+                next_intent: 0,
+                next_hash: 0,
+                desynced: false,
+            }),
+        }
+    }
+}
+
+// Stands in for the keyboard while a replay plays back.
+pub(crate) fn replay_input(
+    tick: Res<Tick>,
+    mut replays: ResMut<ReplaySession>,
+    mut player_intent: ResMut<PlayerIntent>,
+) {
+    if let Some(playback) = &mut replays.playing {
+        while let Some(&(at, intent)) = playback.replay.intents.get(playback.next_intent) {
+            if at > tick.0 {
+                break;
+            }
+            *player_intent = intent;
+            playback.next_intent += 1;
+        }
+    }
+}
+
+pub(crate) fn replay_record(
+    tick: Res<Tick>,
+    player_intent: Res<PlayerIntent>,
+    mut replays: ResMut<ReplaySession>,
+) {
+    if replays.record_to.is_none() {
+        return;
+    }
+    // what the last tick left behind, Pac-Man having taken any press
+    let intents = &mut replays.recording.intents;
+    let last = intents.last()
+        .map(|&(_, intent)| PlayerIntent {pressed: None, ..intent})
+        .unwrap_or_default();
+    if *player_intent != last {
+        intents.push((tick.0, *player_intent));
+    }
+}
+
+// What a replay checks has come out the same: the state of the game, and
+// where everything on the maze is.
+#[derive(SystemParam)]
+pub struct ReplayState<'a> {
+    game: Res<'a, Game>,
+    level: Res<'a, Level>,
+    lives: Res<'a, Lives>,
+    score: Res<'a, Score>,
+    pacmans: Query<'a, (&'static Pacman, &'static Position, &'static Motion)>,
+    ghosts: Query<'a, (&'static Ghost, &'static Position, &'static Motion)>,
+    foods: Query<'a, &'static Food>,
+    energies: Query<'a, &'static Energy>,
+}
+
+impl ReplayState<'_> {
+    fn hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (self.game.mode, self.game.frightened, self.game.ghost_combo).hash(&mut hasher);
+        (self.level.0, self.lives.0, self.score.current).hash(&mut hasher);
+        for (pacman, pos, motion) in self.pacmans.iter() {
+            (pacman.direction, pacman.buffered, *pos, motion.progress.to_bits()).hash(&mut hasher);
+        }
+        let mut ghosts: Vec<_> = self.ghosts.iter().collect();
+        ghosts.sort_by_key(|(ghost, _, _)| ghost.kind as u32);
+        for (ghost, pos, motion) in ghosts {
+            (ghost.direction, ghost.state, ghost.target, *pos, motion.progress.to_bits()).hash(&mut hasher);
+        }
+        (self.foods.iter().count(), self.energies.iter().count()).hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+// Every so often, boils the state of the game down to a hash. A recording
+// keeps it, a playback checks it against the recorded one.
+pub(crate) fn replay_hash(
+    tick: Res<Tick>,
+    state: ReplayState,
+    mut replays: ResMut<ReplaySession>,
+) {
+    if tick.0 % REPLAY_HASH_TICKS != 0 {
+        return;
+    }
+    let hash = state.hash();
+
+    if replays.record_to.is_some() {
+        replays.recording.hashes.push((tick.0, hash));
+    }
+    if let Some(playback) = &mut replays.playing {
+        while let Some(&(at, recorded)) = playback.replay.hashes.get(playback.next_hash) {
+            if at > tick.0 {
+                break;
+            }
+            playback.next_hash += 1;
+            if at == tick.0 && recorded != hash && !playback.desynced {
+                playback.desynced = true;
+                error!("Replay desynced at tick {}", tick.0);
+            }
+        }
+    }
+}
+
+// A recording ends with the game it started with. The next game carries on
+// from a later tick and another random state, which a replay can't start
+// from, so nothing more is recorded after it is written out.
+pub(crate) fn replay_end(maze: Res<Maze>, mut replays: ResMut<ReplaySession>) {
+    save_recording(&maze, &mut replays);
+}
+
+// Writes the recording out when the app closes before the game is over.
+pub(crate) fn replay_save(
+    maze: Res<Maze>,
+    mut replays: ResMut<ReplaySession>,
+    mut exit_reader: Local<EventReader<AppExit>>,
+    exits: Res<Events<AppExit>>,
+) {
+    if exit_reader.iter(&exits).next().is_none() {
+        return;
+    }
+    save_recording(&maze, &mut replays);
+}
+
+fn save_recording(maze: &Maze, replays: &mut ReplaySession) {
+    if let Some(path) = replays.record_to.take() {
+        replays.recording.maze = maze.source.clone();
+        match replays.recording.save(&path) {
+            Ok(()) => info!("Saved replay to {}", path.display()),
+            Err(err) => error!("Couldn't save replay to {}: {}", path.display(), err),
+        }
+    }
+}
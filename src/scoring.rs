@@ -0,0 +1,66 @@
+use bevy::prelude::*;
+
+use crate::game::{self, game_stage, GameStageExt, GameState};
+use crate::maze::Position;
+
+pub const FOOD_POINTS: u32 = 10;
+
+pub const ENERGY_POINTS: u32 = 50;
+
+// 200, 400, 800 then 1600 for each ghost eaten on the same energizer
+pub const GHOST_POINTS: u32 = 200;
+
+#[derive(Default)]
+pub struct Score {
+    pub current: u32,
+    pub high: u32,
+}
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum ScoreReason {
+    Food,
+    Energy,
+    Ghost,
+    Fruit,
+}
+
+// Sent whenever Pac-Man earns points, `Score` is only updated from these.
+pub struct ScoreEvent {
+    pub points: u32,
+    pub reason: ScoreReason,
+    pub position: Position,
+}
+
+// Keeps the `Score`, from the `ScoreEvent`s the rest of the game sends.
+pub struct ScoringPlugin;
+
+impl Plugin for ScoringPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        game::add_core(app);
+        // `HighScorePlugin` may have put one in already, with the best score
+        // from its table
+        if !app.resources().contains::<Score>() {
+            app.add_resource(Score::default());
+        }
+        app.add_game_system(game_stage::MODE, scoring.system())
+            .on_game_state_exit(GameState::Title, score_reset.system());
+    }
+}
+
+fn scoring(
+    mut score: ResMut<Score>,
+    mut score_reader: Local<EventReader<ScoreEvent>>,
+    score_events: Res<Events<ScoreEvent>>,
+) {
+    for event in score_reader.iter(&score_events) {
+        score.current += event.points;
+        if score.current > score.high {
+            score.high = score.current;
+        }
+    }
+}
+
+// Every game starts from nothing, the high score stays.
+fn score_reset(mut score: ResMut<Score>) {
+    score.current = 0;
+}
@@ -0,0 +1,305 @@
+use bevy::{
+    prelude::*,
+    asset::LoadState,
+    audio::{Audio, AudioSource},
+    ecs::{State, SystemParam},
+};
+use std::collections::HashMap;
+use std::convert::TryInto;
+
+use crate::game::{self, ExtraLifeEvent, Game, GameStageExt, GameState, Level};
+use crate::maze::{Maze, Tile};
+use crate::pacman::{Energy, Food};
+use crate::scoring::{ScoreEvent, ScoreReason};
+
+// how many sirens there are, each higher than the last as the dots run out
+pub const SIRENS: usize = 5;
+
+// the levels whose end is marked with the intermission tune
+pub const INTERMISSION_LEVELS: [u32; 5] = [2, 5, 9, 13, 17];
+
+// how much the volume keys turn the volume up or down
+pub const VOLUME_STEP: f32 = 0.1;
+
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum Sound {
+    // a dot or an energizer eaten
+    Waka,
+    // the background loop while the ghosts are out hunting, 0 the lowest
+    Siren(usize),
+    // the background loop while the ghosts are frightened
+    Frightened,
+    GhostEaten,
+    Death,
+    ExtraLife,
+    Intermission,
+}
+
+impl Sound {
+    pub fn all() -> Vec<Sound> {
+        let mut all = vec![
+            Sound::Waka,
+            Sound::Frightened,
+            Sound::GhostEaten,
+            Sound::Death,
+            Sound::ExtraLife,
+            Sound::Intermission,
+        ];
+        all.extend((0..SIRENS).map(Sound::Siren));
+        all
+    }
+    pub fn path(self) -> String {
+        let name = match self {
+            Sound::Waka => "waka".to_string(),
+            Sound::Siren(level) => format!("siren_{}", level + 1),
+            Sound::Frightened => "frightened".to_string(),
+            Sound::GhostEaten => "ghost_eaten".to_string(),
+            Sound::Death => "death".to_string(),
+            Sound::ExtraLife => "extra_life".to_string(),
+            Sound::Intermission => "intermission".to_string(),
+        };
+        format!("sounds/{}.wav", name)
+    }
+}
+
+// Volume goes from 0 to 1. Insert it before adding `SoundPlugin` to start
+// at something other than the default.
+pub struct SoundSettings {
+    pub volume: f32,
+    pub muted: bool,
+}
+
+impl Default for SoundSettings {
+    fn default() -> Self {
+        Self {volume: 0.8, muted: false}
+    }
+}
+
+// bevy's `Audio` plays a sound as it is, at full volume, so the volume is
+// put into the samples: each sound is copied at the current volume once they
+// have all loaded, and again whenever the volume changes.
+#[derive(Default)]
+struct Sounds {
+    // as loaded from the assets
+    loaded: HashMap<Sound, Handle<AudioSource>>,
+    // at `volume`, with how many seconds each lasts
+    scaled: HashMap<Sound, (Handle<AudioSource>, f32)>,
+    volume: Option<f32>,
+}
+
+// Everything a system needs to play the sounds at the current volume.
+#[derive(SystemParam)]
+struct SoundPlayer<'a> {
+    audio: Res<'a, Audio>,
+    settings: Res<'a, SoundSettings>,
+    sounds: Res<'a, Sounds>,
+}
+
+impl SoundPlayer<'_> {
+    fn play(&self, sound: Sound) {
+        if self.settings.muted || self.settings.volume <= 0. {
+            return;
+        }
+        if let Some((handle, _)) = self.sounds.scaled.get(&sound) {
+            self.audio.play(handle.clone());
+        }
+    }
+    fn seconds(&self, sound: Sound) -> f32 {
+        self.sounds.scaled.get(&sound).map_or(0., |(_, seconds)| *seconds)
+    }
+}
+
+// Sound for the game: the effects as things happen, the siren or frightened
+// loop under play, and the M key to mute and -/= for the volume. Goes after
+// `PacmanGamePlugin` and bevy's `DefaultPlugins`, built with the `audio`
+// feature.
+pub struct SoundPlugin;
+
+impl Plugin for SoundPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        game::add_core(app);
+        if !app.resources().contains::<SoundSettings>() {
+            app.add_resource(SoundSettings::default());
+        }
+        app.add_resource(Sounds::default())
+            .add_startup_system(sound_setup.system())
+            .add_system(sound_keys.system())
+            .add_system(sound_volume.system())
+            .add_system(sound_events.system())
+            .add_system(sound_loop.system())
+            .on_game_state_enter(GameState::Dying, death_sound.system())
+            .on_game_state_enter(GameState::LevelClear, intermission_sound.system());
+    }
+}
+
+fn sound_setup(asset_server: Res<AssetServer>, mut sounds: ResMut<Sounds>) {
+    for sound in Sound::all() {
+        sounds.loaded.insert(sound, asset_server.load(sound.path().as_str()));
+    }
+}
+
+fn sound_keys(keyboard_input: Res<Input<KeyCode>>, mut settings: ResMut<SoundSettings>) {
+    if keyboard_input.just_pressed(KeyCode::M) {
+        settings.muted = !settings.muted;
+        info!("Sound {}", if settings.muted { "muted" } else { "on" });
+    }
+    let step = if keyboard_input.just_pressed(KeyCode::Minus) || keyboard_input.just_pressed(KeyCode::NumpadSubtract) {
+        -VOLUME_STEP
+    } else if keyboard_input.just_pressed(KeyCode::Equals) || keyboard_input.just_pressed(KeyCode::NumpadAdd) {
+        VOLUME_STEP
+    } else {
+        return;
+    };
+    settings.volume = (settings.volume + step).max(0.).min(1.);
+    info!("Volume {:.0}%", settings.volume * 100.);
+}
+
+fn sound_volume(
+    asset_server: Res<AssetServer>,
+    settings: Res<SoundSettings>,
+    mut sounds: ResMut<Sounds>,
+    mut sources: ResMut<Assets<AudioSource>>,
+) {
+    if sounds.volume == Some(settings.volume) {
+        return;
+    }
+    // a sound that can't be played is left out from then on, so it is only
+    // reported the once and the rest still play
+    let failed: Vec<Sound> = sounds.loaded.iter()
+        .filter(|(_, handle)| asset_server.get_load_state(*handle) == LoadState::Failed)
+        .map(|(sound, _)| *sound)
+        .collect();
+    for sound in failed {
+        warn!("{} couldn't be loaded, it won't be played", sound.path());
+        sounds.loaded.remove(&sound);
+    }
+    // wait for the rest, a sound missing halfway through a game is odder
+    // than no sound until they are all there
+    if sounds.loaded.values().any(|handle| sources.get(handle).is_none()) {
+        return;
+    }
+    let mut scaled = HashMap::new();
+    let mut unplayable = Vec::new();
+    for (sound, handle) in sounds.loaded.iter() {
+        match scale_wav(&sources.get(handle).unwrap().bytes, settings.volume) {
+            Some((bytes, seconds)) => {
+                scaled.insert(*sound, (bytes, seconds));
+            }
+            None => {
+                warn!("{} isn't a 16 bit WAV file, it won't be played", sound.path());
+                unplayable.push(*sound);
+            }
+        }
+    }
+    for sound in unplayable {
+        sounds.loaded.remove(&sound);
+    }
+    sounds.scaled = scaled.into_iter()
+        .map(|(sound, (bytes, seconds))| (sound, (sources.add(AudioSource {bytes: bytes.into()}), seconds)))
+        .collect();
+    sounds.volume = Some(settings.volume);
+}
+
+// A copy of a 16 bit PCM WAV file with its samples scaled by `volume`, and
+// how long it plays for.
+fn scale_wav(wav: &[u8], volume: f32) -> Option<(Vec<u8>, f32)> {
+    if wav.len() < 12 || &wav[0..4] != b"RIFF" || &wav[8..12] != b"WAVE" {
+        return None;
+    }
+    let mut bytes = wav.to_vec();
+    let mut byte_rate = None;
+    let mut at = 12;
+    while at + 8 <= wav.len() {
+        let id = &wav[at..at + 4];
+        let size = u32::from_le_bytes(wav[at + 4..at + 8].try_into().unwrap()) as usize;
+        let body = at + 8..(at + 8 + size).min(wav.len());
+        if id == b"fmt " && body.len() >= 16 {
+            let format = u16::from_le_bytes(wav[body.start..body.start + 2].try_into().unwrap());
+            let bits = u16::from_le_bytes(wav[body.start + 14..body.start + 16].try_into().unwrap());
+            if format != 1 || bits != 16 {
+                return None;
+            }
+            byte_rate = Some(u32::from_le_bytes(wav[body.start + 8..body.start + 12].try_into().unwrap()));
+        } else if id == b"data" {
+            let byte_rate = byte_rate?;
+            for sample in bytes[body.clone()].chunks_exact_mut(2) {
+                let value = i16::from_le_bytes([sample[0], sample[1]]) as f32 * volume;
+                sample.copy_from_slice(&(value as i16).to_le_bytes());
+            }
+            return Some((bytes, body.len() as f32 / byte_rate as f32));
+        }
+        // chunks are padded to an even length
+        at = body.end + size % 2;
+    }
+    None
+}
+
+fn sound_events(
+    time: Res<Time>,
+    player: SoundPlayer,
+    // seconds until the last waka is over, dots eaten quicker than that don't
+    // start another on top of it
+    mut waka_left: Local<f32>,
+    mut score_reader: Local<EventReader<ScoreEvent>>,
+    score_events: Res<Events<ScoreEvent>>,
+    mut extra_life_reader: Local<EventReader<ExtraLifeEvent>>,
+    extra_life_events: Res<Events<ExtraLifeEvent>>,
+) {
+    *waka_left -= time.delta_seconds();
+    for event in score_reader.iter(&score_events) {
+        match event.reason {
+            ScoreReason::Food | ScoreReason::Energy if *waka_left <= 0. => {
+                player.play(Sound::Waka);
+                *waka_left = player.seconds(Sound::Waka);
+            }
+            ScoreReason::Ghost => player.play(Sound::GhostEaten),
+            _ => {}
+        }
+    }
+    for _ in extra_life_reader.iter(&extra_life_events) {
+        player.play(Sound::ExtraLife);
+    }
+}
+
+// Keeps the siren or the frightened loop going while the game is being
+// played. bevy's `Audio` can't loop a sound or stop one, so each is played
+// again as it ends, and a change of loop waits for the last one to finish.
+fn sound_loop(
+    time: Res<Time>,
+    player: SoundPlayer,
+    state: Res<State<GameState>>,
+    game: Res<Game>,
+    maze: Res<Maze>,
+    // seconds until the loop playing now goes round again
+    mut loop_left: Local<f32>,
+    dots: Query<Entity, Or<(With<Food>, With<Energy>)>>,
+) {
+    let sound = if *state.current() != GameState::Playing {
+        None
+    } else if game.frightened {
+        Some(Sound::Frightened)
+    } else {
+        let total = maze.tiles.iter().filter(|tile| tile.has_dot() || **tile == Tile::Energizer).count();
+        let left = dots.iter().count();
+        let eaten = total.saturating_sub(left) as f32 / total.max(1) as f32;
+        Some(Sound::Siren(((eaten * SIRENS as f32) as usize).min(SIRENS - 1)))
+    };
+    *loop_left -= time.delta_seconds();
+    if *loop_left > 0. {
+        return;
+    }
+    if let Some(sound) = sound {
+        player.play(sound);
+        *loop_left = player.seconds(sound);
+    }
+}
+
+fn death_sound(player: SoundPlayer) {
+    player.play(Sound::Death);
+}
+
+fn intermission_sound(player: SoundPlayer, level: Res<Level>) {
+    if INTERMISSION_LEVELS.contains(&level.0) {
+        player.play(Sound::Intermission);
+    }
+}
@@ -0,0 +1,339 @@
+// Gameplay tests. Each one builds the game headless on a small maze, steps
+// it a number of ticks with a scripted set of key presses, and checks where
+// things ended up.
+
+use bevy::{prelude::*, ecs::State};
+use pacman::{
+    fruit::{Fruit, FruitDots, FruitKind, FRUIT_DOTS},
+    game::{Game, GameState, Lives, STARTING_LIVES},
+    ghost::{Ghost, GhostKind, GhostState, Mode},
+    maze::{Direction, Maze, Position},
+    pacman::{Food, Pacman, PlayerIntent},
+    replay::ReplaySession,
+    scoring::{Score, ENERGY_POINTS, FOOD_POINTS},
+    GameConfig, GhostAiPlugin, MazePlugin, PacmanGamePlugin, PacmanPlugin, ScoringPlugin,
+};
+
+// Pac-Man starts out heading right along a corridor that wraps around at
+// both ends, with an energizer right in front of it.
+const TUNNEL_MAZE: &str = "\
+#############
+#.....b.....#
+#.####-####.#
+#.#  ipc  #.#
+#.#########.#
+.......Po....
+#.#########.#
+#...........#
+#############
+";
+
+// Pac-Man is shut away from the ghosts, so nothing can catch it.
+const SHUT_IN_MAZE: &str = "\
+#############
+#.....b.....#
+#.####-####.#
+#.#  ipc  #.#
+#.#########.#
+#...........#
+#############
+#P..........#
+#############
+";
+
+// The same, with a fruit tile at the end of Pac-Man's corridor.
+const FRUIT_MAZE: &str = "\
+#############
+#.....b.....#
+#.####-####.#
+#.#  ipc  #.#
+#.#########.#
+#...........#
+#############
+#P.........F#
+#############
+";
+
+// Ticks once per update, straight into the first game, with the same seed
+// every time.
+fn test_config() -> GameConfig {
+    GameConfig {realtime: false, title: false, seed: 0, ..Default::default()}
+}
+
+struct TestGame {
+    app: App,
+    script: Vec<(u64, Direction)>,
+    tick: u64,
+}
+impl TestGame {
+    fn new(maze: &str) -> Self {
+        Self::with_config(maze, test_config())
+    }
+    fn with_config(maze: &str, config: GameConfig) -> Self {
+        let maze = Maze::parse(maze).unwrap();
+        let mut builder = App::build();
+        builder.add_resource(config)
+            .add_resource(maze)
+            .add_plugins(MinimalPlugins)
+            .add_plugin(PacmanGamePlugin);
+        let mut game = Self {app: std::mem::take(&mut builder.app), script: Vec::new(), tick: 0};
+        // past READY!
+        while game.state() != GameState::Playing {
+            game.app.update();
+        }
+        game
+    }
+    // Key presses to make, by tick.
+    fn with_script(mut self, script: &[(u64, Direction)]) -> Self {
+        self.script = script.to_vec();
+        self
+    }
+    fn step(&mut self, ticks: u64) {
+        for _ in 0..ticks {
+            self.press_scripted();
+            self.app.update();
+            self.tick += 1;
+        }
+    }
+    fn press_scripted(&mut self) {
+        let tick = self.tick;
+        if let Some(&(_, dir)) = self.script.iter().find(|(at, _)| *at == tick) {
+            self.app.resources.get_mut::<PlayerIntent>().unwrap().pressed = Some(dir);
+        }
+    }
+
+    fn maze(&self) -> Maze {
+        self.app.resources.get::<Maze>().unwrap().clone()
+    }
+    fn pacman(&self) -> Position {
+        *self.app.world.query::<(&Pacman, &Position)>().next().unwrap().1
+    }
+    fn ghost(&self, kind: GhostKind) -> (GhostState, Position) {
+        self.app.world.query::<(&Ghost, &Position)>()
+            .find(|(ghost, _)| ghost.kind == kind)
+            .map(|(ghost, pos)| (ghost.state, *pos))
+            .unwrap()
+    }
+    fn fruit(&self) -> usize {
+        self.app.world.query::<&Fruit>().count()
+    }
+    fn food(&self) -> usize {
+        self.app.world.query::<&Food>().count()
+    }
+    fn score(&self) -> u32 {
+        self.app.resources.get::<Score>().unwrap().current
+    }
+    fn lives(&self) -> u32 {
+        self.app.resources.get::<Lives>().unwrap().0
+    }
+    fn mode(&self) -> Mode {
+        self.app.resources.get::<Game>().unwrap().mode
+    }
+    fn state(&self) -> GameState {
+        *self.app.resources.get::<State<GameState>>().unwrap().current()
+    }
+    fn set_state(&mut self, state: GameState) {
+        self.app.resources.get_mut::<State<GameState>>().unwrap().set_next(state).unwrap();
+    }
+    fn frightened(&self) -> bool {
+        self.app.resources.get::<Game>().unwrap().frightened
+    }
+}
+
+#[test]
+fn pacman_wraps_through_the_tunnel() {
+    let mut game = TestGame::new(TUNNEL_MAZE);
+    let mut wrapped = false;
+    for _ in 0..60 {
+        let before = game.pacman();
+        game.step(1);
+        let after = game.pacman();
+        wrapped |= before == Position{x: 12, y: 5} && after == Position{x: 0, y: 5};
+    }
+    assert!(wrapped, "Pac-Man ended up on {:?}", game.pacman());
+}
+
+#[test]
+fn pacman_turns_when_asked() {
+    let mut game = TestGame::new(TUNNEL_MAZE).with_script(&[(0, Direction::Left)]);
+    game.step(30);
+    let pos = game.pacman();
+    assert_eq!(pos.y, 5);
+    assert!(pos.x < 7, "Pac-Man ended up on {:?}", pos);
+}
+
+#[test]
+fn eating_dots_scores_points() {
+    let mut game = TestGame::new(SHUT_IN_MAZE);
+    let food = game.food();
+    game.step(60);
+    let eaten = food - game.food();
+    assert!(eaten > 0);
+    assert_eq!(game.score(), eaten as u32 * FOOD_POINTS);
+}
+
+#[test]
+fn energizer_frightens_the_ghosts() {
+    let mut game = TestGame::new(TUNNEL_MAZE);
+    game.step(24);
+    assert!(game.frightened());
+    assert_eq!(game.ghost(GhostKind::Blinky).0, GhostState::Frightened);
+    assert!(game.score() >= ENERGY_POINTS);
+    // the scatter/chase schedule waits for the fright to end
+    assert_eq!(game.mode(), Mode::Scatter);
+}
+
+#[test]
+fn ghosts_out_in_the_maze_stay_out_of_the_house() {
+    let mut game = TestGame::new(SHUT_IN_MAZE);
+    let maze = game.maze();
+    for _ in 0..1200 {
+        game.step(1);
+        let (state, pos) = game.ghost(GhostKind::Blinky);
+        if state == GhostState::Normal || state == GhostState::Frightened {
+            assert!(!pos.in_ghost_house(&maze), "Blinky got into the house at {:?}", pos);
+        }
+    }
+}
+
+#[test]
+fn scatter_turns_to_chase() {
+    let mut game = TestGame::new(SHUT_IN_MAZE);
+    assert_eq!(game.mode(), Mode::Scatter);
+    game.step(450);
+    assert_eq!(game.mode(), Mode::Chase);
+    assert_eq!(game.lives(), STARTING_LIVES);
+}
+
+#[test]
+fn same_input_same_game() {
+    let script = [(10, Direction::Left), (50, Direction::Right), (90, Direction::Left)];
+    let mut first = TestGame::new(TUNNEL_MAZE).with_script(&script);
+    let mut second = TestGame::new(TUNNEL_MAZE).with_script(&script);
+    for _ in 0..600 {
+        first.step(1);
+        second.step(1);
+        assert_eq!(first.pacman(), second.pacman());
+        assert_eq!(first.ghost(GhostKind::Inky), second.ghost(GhostKind::Inky));
+    }
+}
+
+#[test]
+fn pausing_stops_everything() {
+    let mut game = TestGame::new(TUNNEL_MAZE);
+    game.set_state(GameState::Paused);
+    game.step(1);
+    let pacman = game.pacman();
+    let blinky = game.ghost(GhostKind::Blinky);
+    game.step(60);
+    assert_eq!(game.state(), GameState::Paused);
+    assert_eq!(game.pacman(), pacman);
+    assert_eq!(game.ghost(GhostKind::Blinky), blinky);
+
+    game.set_state(GameState::Playing);
+    game.step(60);
+    assert_ne!(game.pacman(), pacman);
+}
+
+#[test]
+fn fruit_shows_up_and_gets_eaten() {
+    let mut game = TestGame::new(FRUIT_MAZE);
+    game.app.resources.get_mut::<FruitDots>().unwrap().0 = FRUIT_DOTS[0] - 1;
+    let food = game.food();
+    assert_eq!(game.fruit(), 0);
+    game.step(20);
+    assert_eq!(game.fruit(), 1);
+    game.step(120);
+    assert_eq!(game.fruit(), 0);
+    let eaten = (food - game.food()) as u32;
+    assert_eq!(game.score(), eaten * FOOD_POINTS + FruitKind::Cherry.points());
+}
+
+#[test]
+fn pacman_turns_back_straight_away() {
+    let mut game = TestGame::new(SHUT_IN_MAZE);
+    game.step(20);
+    let before = game.pacman();
+    assert!(before.x > 1);
+    game.app.resources.get_mut::<PlayerIntent>().unwrap().pressed = Some(Direction::Left);
+    game.step(1);
+    // back on the tile it was leaving, rather than going on to the next
+    assert!(game.pacman().x < before.x, "Pac-Man ended up on {:?}", game.pacman());
+}
+
+#[test]
+fn maze_comes_from_the_config() {
+    let path = std::env::temp_dir().join(format!("pacman-test-{}.maze", std::process::id()));
+    std::fs::write(&path, SHUT_IN_MAZE).unwrap();
+    let mut builder = App::build();
+    builder.add_resource(GameConfig {maze: Some(path.clone()), ..test_config()})
+        .add_plugins(MinimalPlugins)
+        .add_plugin(PacmanGamePlugin);
+    let mut app = std::mem::take(&mut builder.app);
+    app.update();
+    std::fs::remove_file(&path).unwrap();
+    let maze = app.resources.get::<Maze>().unwrap();
+    assert_eq!((maze.width, maze.height), (13, 9));
+}
+
+#[test]
+fn the_same_turn_twice_is_recorded_twice() {
+    let record = std::env::temp_dir().join(format!("pacman-test-{}.replay", std::process::id()));
+    let config = GameConfig {record: Some(record), ..test_config()};
+    let mut game = TestGame::with_config(TUNNEL_MAZE, config);
+    for _ in 0..2 {
+        game.app.resources.get_mut::<PlayerIntent>().unwrap().pressed = Some(Direction::Left);
+        game.step(1);
+    }
+    let replays = game.app.resources.get::<ReplaySession>().unwrap();
+    let presses = replays.recording.intents.iter()
+        .filter(|(_, intent)| intent.pressed == Some(Direction::Left))
+        .count();
+    assert_eq!(presses, 2);
+}
+
+#[test]
+fn plugins_on_their_own_build_the_level() {
+    let maze = Maze::parse(SHUT_IN_MAZE).unwrap();
+    let dots = maze.tiles.iter().filter(|tile| tile.has_dot()).count();
+    let mut builder = App::build();
+    builder.add_resource(test_config())
+        .add_resource(maze)
+        .add_plugins(MinimalPlugins)
+        .add_plugin(MazePlugin)
+        .add_plugin(PacmanPlugin)
+        .add_plugin(GhostAiPlugin)
+        .add_plugin(ScoringPlugin);
+    let mut app = std::mem::take(&mut builder.app);
+    app.update();
+    assert_eq!(app.world.query::<&Pacman>().count(), 1);
+    assert_eq!(app.world.query::<&Ghost>().count(), 4);
+    assert_eq!(app.world.query::<&Food>().count(), dots);
+
+    // and play it, past READY!
+    for _ in 0..600 {
+        app.update();
+    }
+    assert_eq!(*app.resources.get::<State<GameState>>().unwrap().current(), GameState::Playing);
+    assert!(app.resources.get::<Score>().unwrap().current > 0);
+}
+
+#[test]
+fn maze_and_pacman_alone_play_a_level() {
+    let maze = Maze::parse(SHUT_IN_MAZE).unwrap();
+    let dots = maze.tiles.iter().filter(|tile| tile.has_dot()).count();
+    let mut builder = App::build();
+    builder.add_resource(test_config())
+        .add_resource(maze)
+        .add_plugins(MinimalPlugins)
+        .add_plugin(MazePlugin)
+        .add_plugin(PacmanPlugin);
+    let mut app = std::mem::take(&mut builder.app);
+    for _ in 0..600 {
+        app.update();
+    }
+    // no score and no high score table to keep, but the dots still go
+    assert!(app.resources.get::<Score>().is_none());
+    assert_eq!(*app.resources.get::<State<GameState>>().unwrap().current(), GameState::Playing);
+    assert!(app.world.query::<&Food>().count() < dots);
+}
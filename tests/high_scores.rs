@@ -0,0 +1,54 @@
+// The high score table on disk.
+
+use pacman::high_scores::{HighScore, HighScores, HIGH_SCORE_ENTRIES};
+use std::path::PathBuf;
+
+fn table_path(name: &str) -> PathBuf {
+    let path = std::env::temp_dir()
+        .join(format!("pacman-{}", std::process::id()))
+        .join(name);
+    let _ = std::fs::remove_file(&path);
+    path
+}
+
+fn entry(initials: &str, score: u32) -> HighScore {
+    HighScore {initials: initials.to_string(), score, level: 1}
+}
+
+#[test]
+fn table_keeps_the_best_scores() {
+    let mut table = HighScores::default();
+    for score in 1..=HIGH_SCORE_ENTRIES as u32 + 5 {
+        table.insert(entry("AAA", score * 100));
+    }
+    assert_eq!(table.entries.len(), HIGH_SCORE_ENTRIES);
+    assert_eq!(table.best(), 1500);
+    assert!(!table.qualifies(600));
+    assert!(table.qualifies(601));
+}
+
+#[test]
+fn table_survives_a_restart() {
+    let path = table_path("saved.ron");
+    let mut table = HighScores::load(Some(path.clone()));
+    table.insert(entry("ABC", 1230));
+    table.insert(entry("XYZ", 4560));
+    table.save().unwrap();
+
+    let table = HighScores::load(Some(path));
+    assert_eq!(table.entries, vec![entry("XYZ", 4560), entry("ABC", 1230)]);
+}
+
+#[test]
+fn corrupt_table_is_replaced() {
+    let path = table_path("corrupt.ron");
+    let mut table = HighScores::load(Some(path.clone()));
+    table.insert(entry("ABC", 1230));
+    table.save().unwrap();
+    let tampered = std::fs::read_to_string(&path).unwrap().replace("1230", "9990");
+    std::fs::write(&path, tampered).unwrap();
+
+    let table = HighScores::load(Some(path.clone()));
+    assert!(table.entries.is_empty());
+    assert!(HighScores::read(&path).unwrap().is_empty());
+}
@@ -0,0 +1,32 @@
+// Reading mazes from text.
+
+use pacman::maze::Maze;
+
+fn classic() -> String {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/mazes/classic.maze");
+    std::fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn trailing_filler_can_be_trimmed() {
+    let source = classic();
+    let trimmed: String = source.lines().map(|line| format!("{}\n", line.trim_end())).collect();
+    let maze = Maze::parse(&source).unwrap();
+    let trimmed = Maze::parse(&trimmed).unwrap();
+    assert_eq!((trimmed.width, trimmed.height), (maze.width, maze.height));
+    assert_eq!(trimmed.tiles, maze.tiles);
+    assert_eq!(trimmed.tunnels, maze.tunnels);
+}
+
+#[test]
+fn errors_point_at_the_tile_at_fault() {
+    let source = classic();
+    let unknown = Maze::parse(&source.replacen('.', "x", 1)).unwrap_err();
+    let (line, column) = unknown.position.unwrap();
+    assert_eq!(source.lines().nth(line - 1).unwrap().chars().nth(column - 1), Some('.'));
+
+    // a marker missing from the whole maze is nowhere in particular
+    let no_pacman = Maze::parse(&source.replace('P', " ")).unwrap_err();
+    assert_eq!(no_pacman.position, None);
+    assert_eq!(no_pacman.to_string(), "no spawn tile 'P' for Pac-Man");
+}
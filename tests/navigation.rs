@@ -0,0 +1,41 @@
+// Shortest-path queries on a small maze.
+
+use pacman::maze::{Actor, Maze, Position};
+
+// One way round from Pac-Man's corner to the middle of the upper corridor,
+// the other is cut off by the wall between the two halves.
+const SPLIT_MAZE: &str = "\
+#############
+#.....b.....#
+#.####-####.#
+#.#  ipc  #.#
+#.#########.#
+#.....#.....#
+#.###.#.###.#
+#P..........#
+#############
+";
+
+#[test]
+fn path_goes_the_shortest_way() {
+    let maze = Maze::parse(SPLIT_MAZE).unwrap();
+    let from = Position{x: 1, y: 7};
+    let to = Position{x: 7, y: 5};
+    let path = maze.path(Actor::Pacman, from, to).unwrap();
+    let expected: Vec<Position> = (2..=7).map(|x| Position{x, y: 7})
+        .chain(vec![Position{x: 7, y: 6}, Position{x: 7, y: 5}])
+        .collect();
+    assert_eq!(path, expected);
+    assert_eq!(path.len() as u32, maze.distance(Actor::Pacman, from, to).unwrap());
+    assert_eq!(maze.next_step(Actor::Pacman, from, to), Some(Position{x: 2, y: 7}));
+}
+
+#[test]
+fn no_step_when_there_or_walled_off() {
+    let maze = Maze::parse(SPLIT_MAZE).unwrap();
+    let here = Position{x: 1, y: 7};
+    assert_eq!(maze.next_step(Actor::Pacman, here, here), None);
+    assert_eq!(maze.path(Actor::Pacman, here, here), Some(Vec::new()));
+    // the ghost house is shut to Pac-Man
+    assert_eq!(maze.path(Actor::Pacman, here, Position{x: 6, y: 3}), None);
+}
@@ -0,0 +1,86 @@
+#!/usr/bin/env python3
+# Synthesizes the game's sound effects into assets/sounds as 16-bit mono WAV.
+# Run from the repository root after changing any of them.
+
+import math
+import os
+import struct
+import wave
+
+RATE = 22050
+OUT = os.path.join("assets", "sounds")
+
+
+def tone(freq, seconds, volume=0.5, shape="square"):
+    # `freq` is a function of the time in the sound, so sweeps are easy
+    samples = []
+    phase = 0.0
+    for i in range(int(seconds * RATE)):
+        t = i / RATE
+        phase += freq(t) / RATE
+        x = phase % 1.0
+        if shape == "square":
+            v = 1.0 if x < 0.5 else -1.0
+        elif shape == "triangle":
+            v = 4.0 * abs(x - 0.5) - 1.0
+        else:
+            v = math.sin(2 * math.pi * x)
+        # a few milliseconds of fade at either end to stop clicks
+        fade = min(1.0, i / (0.004 * RATE), (seconds * RATE - i) / (0.004 * RATE))
+        samples.append(v * volume * fade)
+    return samples
+
+
+def silence(seconds):
+    return [0.0] * int(seconds * RATE)
+
+
+def write(name, samples):
+    with wave.open(os.path.join(OUT, name + ".wav"), "wb") as out:
+        out.setnchannels(1)
+        out.setsampwidth(2)
+        out.setframerate(RATE)
+        out.writeframes(b"".join(
+            struct.pack("<h", int(max(-1.0, min(1.0, s)) * 32767)) for s in samples))
+
+
+def main():
+    os.makedirs(OUT, exist_ok=True)
+
+    # down and back up, once per dot
+    write("waka", tone(lambda t: 500 - 1800 * t if t < 0.07 else 374 + 1800 * (t - 0.07), 0.14,
+                       0.3, "triangle"))
+
+    # the siren rises and falls, each one a little higher as the dots run out
+    for level in range(5):
+        base = 380 + 70 * level
+        write("siren_%d" % (level + 1),
+              tone(lambda t, base=base: base + 200 * math.sin(math.pi * t / 0.4), 0.4, 0.2, "sine"))
+
+    write("frightened", tone(lambda t: 180 + 500 * ((t * 7.5) % 1.0), 0.4, 0.2, "sine"))
+
+    write("ghost_eaten", tone(lambda t: 200 + 2400 * t, 0.5, 0.3, "square"))
+
+    death = []
+    for n in range(9):
+        top = 900 - 70 * n
+        death += tone(lambda t, top=top: top - 1500 * t, 0.14, 0.3, "triangle")
+    death += tone(lambda t: 120, 0.1, 0.3, "square") + silence(0.05) + tone(lambda t: 100, 0.1, 0.3, "square")
+    write("death", death)
+
+    extra_life = []
+    for _ in range(6):
+        extra_life += tone(lambda t: 1760, 0.07, 0.25, "square") + silence(0.05)
+    write("extra_life", extra_life)
+
+    # a short tune for the break between levels
+    notes = [(523, 0.15), (659, 0.15), (784, 0.15), (1047, 0.3), (784, 0.15), (1047, 0.45),
+             (880, 0.15), (698, 0.15), (880, 0.15), (1047, 0.6)]
+    intermission = []
+    for freq, seconds in notes:
+        intermission += tone(lambda t, freq=freq: freq, seconds * 0.9, 0.25, "square") + silence(seconds * 0.1)
+    write("intermission", intermission)
+
+
+if __name__ == "__main__":
+    main()